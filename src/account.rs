@@ -0,0 +1,101 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use chrono::Local;
+
+const DEFAULT_PASSWORD: &str = "aaa";
+
+/// Username, password and e-mail used to log in, or to register when the
+/// login is refused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    pub email: String,
+}
+
+impl Credentials {
+    /// Without a username a fresh `dirkle<timestamp>` account is made up,
+    /// which never has a saved game to resume.
+    pub fn new(username: Option<String>, password: Option<String>, email: Option<String>) -> Self {
+        let given = |value: Option<String>| value.filter(|v| !v.is_empty());
+        Self {
+            username: given(username)
+                .unwrap_or_else(|| format!("dirkle{}", Local::now().format("%Y%m%d%H%M%S"))),
+            password: given(password).unwrap_or_else(|| DEFAULT_PASSWORD.to_string()),
+            email: email.unwrap_or_default(),
+        }
+    }
+
+    pub fn login(&self) -> ClientMessage {
+        ClientMessage::Login {
+            username: self.username.clone(),
+            password: self.password.clone(),
+        }
+    }
+
+    pub fn register(&self) -> ClientMessage {
+        ClientMessage::Register {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            email: self.email.clone(),
+        }
+    }
+}
+
+/// The account the bot plays on, whether this connection is logged in and
+/// which game it is playing.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub credentials: Credentials,
+    /// Name the server confirmed with `login_success`.
+    pub logged_in: Option<String>,
+    /// Game id of the last `play` sent, until that game ends.
+    pub playing: Option<String>,
+    /// Why the last game ended, e.g. the cause of death, from `game_ended`.
+    pub ended: Option<String>,
+}
+
+impl Account {
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            logged_in: None,
+            playing: None,
+            ended: None,
+        }
+    }
+
+    pub fn update(&mut self, msg: &ServerMessage) {
+        match msg {
+            ServerMessage::LoginSuccess(success) => {
+                self.logged_in = Some(
+                    success
+                        .username
+                        .clone()
+                        .unwrap_or_else(|| self.credentials.username.clone()),
+                );
+            }
+            ServerMessage::GameEnded(fields) => {
+                self.playing = None;
+                self.ended = ["message", "reason"]
+                    .iter()
+                    .find_map(|key| fields.get(*key)?.as_str())
+                    .filter(|text| !text.is_empty())
+                    .map(str::to_string);
+            }
+            ServerMessage::GoLobby => self.playing = None,
+            _ => {}
+        }
+    }
+
+    pub fn sent(&mut self, msg: &ClientMessage) {
+        if let ClientMessage::Play { game_id } = msg {
+            self.playing = Some(game_id.clone());
+            self.ended = None;
+        }
+    }
+
+    /// A new connection starts logged out; the game is kept to resume it.
+    pub fn disconnected(&mut self) {
+        self.logged_in = None;
+    }
+}
@@ -1,225 +1,129 @@
-use crate::logger::Logger;
-use crate::map::MapState;
-use crate::protocol::GameMessage;
-use serde_json::Value;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum Routine {
-    Idle,
-    Init,
-    StartGame,
-    StartSeededGame,
-}
-
-pub async fn execute_routine(
-    routine: Routine,
-    current: Option<&Value>,
-    _next: Option<&Value>,
-    map_state: &Arc<Mutex<MapState>>,
-    logger: &Logger,
-) -> (Routine, Vec<String>) {
-    let msg = if let Some(current_val) = current {
-        match serde_json::from_value::<GameMessage>(current_val.clone()) {
-            Ok(m) => Some(m),
-            Err(_) => return (routine, vec![]),
-        }
-    } else {
-        None
-    };
-
-    let msg_type = msg.as_ref().map(|m| m.msg.as_str());
-    let msg_title = msg.as_ref().and_then(|m| m.title.as_deref());
-
-    logger
-        .log(&format!(
-            "[ROUTIN]: Executing routine with message '{:?}'\n",
-            msg_type
-        ))
-        .await;
-
-    if let Some(ref m) = msg {
-        if m.msg == "map" {
-            if let Some(cells) = &m.cells {
-                let mut map = map_state.lock().await;
-                map.update_map(cells, logger).await;
-                let mut buf = Vec::new();
-                if map.print_map(&mut buf).is_ok() {
-                    if let Ok(s) = String::from_utf8(buf) {
-                        logger.log(&s).await;
-                    }
-                }
+use crate::map::LevelId;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::{Context, Launch, Registry};
+use crate::scheduler::Scheduler;
+
+/// Folds a server message into the shared game state before any routine
+/// sees it.
+pub async fn update_state(current: &ServerMessage, ctx: &Context) {
+    let logger = &ctx.logger;
+
+    if let ServerMessage::Player(player_msg) = current {
+        let mut player = ctx.player_state.lock().await;
+        player.update(player_msg);
+        logger
+            .log(&format!("[PLAYER]: {}\n", player.summary()))
+            .await;
+
+        if player_msg.place.is_some() || player_msg.depth.is_some() {
+            let level = LevelId {
+                place: player.place.clone(),
+                depth: player.depth,
+            };
+            let mut map = ctx.map_state.lock().await;
+            if map.set_level(level) {
+                logger
+                    .log(&format!("[PLAYER]: entered level {}\n", map.current_id()))
+                    .await;
             }
         }
     }
 
-    logger
-        .log(&format!(
-            "[ROUTIN]: Executing {:?} routine logic\n",
-            routine
-        ))
-        .await;
+    if let ServerMessage::Msgs(msgs) = current {
+        let mut log = ctx.message_log.lock().await;
+        for entry in log.update(msgs) {
+            logger
+                .log(&format!(
+                    "[MSGLOG]: {} {:?}: {}\n",
+                    entry.turn,
+                    entry.channel,
+                    entry.text()
+                ))
+                .await;
+        }
+    }
 
-    match routine {
-        Routine::Idle => (Routine::Idle, vec![]),
-        Routine::Init => match msg_type {
-            Some("html") => (Routine::StartSeededGame, vec![]),
-            Some("lobby_clear") | Some("lobby_complete") => (Routine::Init, vec![]),
-            _ => (Routine::Idle, vec![]),
-        },
-        Routine::StartGame => match msg_type {
-            None => (Routine::StartGame, vec![command::register_random()]),
-            Some("login_success") => (Routine::StartGame, vec![command::play()]),
-            Some("ui-push") => match msg_title {
-                Some(title) if title.contains("species") => {
-                    (Routine::StartGame, vec![command::send_text("f")])
-                }
-                Some(title) if title.contains("background") => {
-                    (Routine::StartGame, vec![command::send_text("f")])
-                }
-                Some(title) if title.contains("Welcome") => {
-                    logger
-                        .log("[ROUTIN]: StartSeededGame successfully finished\n")
-                        .await;
-                    (Routine::Idle, vec![command::send_text("f")])
-                }
-                _ => {
-                    logger
-                        .log("[ROUTIN]: StartSeededGame aborted, title not recognized\n")
-                        .await;
-                    (Routine::Idle, vec![])
-                }
-            },
-            Some("html")
-            | Some("set_game_links")
-            | Some("game_client")
-            | Some("game_started")
-            | Some("chat")
-            | Some("version")
-            | Some("options")
-            | Some("layout")
-            | Some("ui-state-sync")
-            | Some("ui-state")
-            | Some("ui_state")
-            | Some("ui-pop")
-            | Some("player")
-            | Some("update_spectators") => (Routine::StartGame, vec![]),
-            _ => (Routine::Idle, vec![]),
-        },
-        Routine::StartSeededGame => match msg_type {
-            None => (Routine::StartSeededGame, vec![command::register_random()]),
-            Some("login_success") => (Routine::StartSeededGame, vec![command::play_seeded()]),
-            Some("ui-push") => match msg_title {
-                Some(title) if title.contains("Play a game with a custom seed") => (
-                    Routine::StartSeededGame,
-                    vec![
-                        command::send_text("-"),
-                        command::send_text("122333"),
-                        command::send_keycode(13),
-                    ],
-                ),
-                Some(title) if title.contains("Please select your species") => {
-                    (Routine::StartSeededGame, vec![command::send_text("f")])
-                }
-                Some(title) if title.contains("Please select your background") => {
-                    (Routine::StartSeededGame, vec![command::send_text("f")])
-                }
-                Some(title) if title.contains("Welcome") => {
-                    logger
-                        .log("[ROUTIN]: StartSeededGame successfully finished\n")
-                        .await;
-                    (Routine::Idle, vec![command::send_text("f")])
-                }
-                _ => {
-                    logger
-                        .log("[ROUTIN]: StartSeededGame aborted, title not recognized\n")
-                        .await;
-                    (Routine::Idle, vec![])
-                }
-            },
-            Some("html")
-            | Some("set_game_links")
-            | Some("game_client")
-            | Some("game_started")
-            | Some("chat")
-            | Some("version")
-            | Some("options")
-            | Some("layout")
-            | Some("ui-state-sync")
-            | Some("ui-state")
-            | Some("ui_state")
-            | Some("ui-pop")
-            | Some("player")
-            | Some("text_cursor")
-            | Some("update_spectators") => (Routine::StartSeededGame, vec![]),
-            _ => (Routine::Idle, vec![]),
-        },
+    ctx.ui_stack.lock().await.update(current);
+    ctx.account.lock().await.update(current);
+    ctx.input.lock().await.update(current);
+
+    if let ServerMessage::Map(map_msg) = current {
+        let mut map = ctx.map_state.lock().await;
+        map.update_map(map_msg, logger).await;
+        let mut buf = Vec::new();
+        if map.print_map(&mut buf).is_ok()
+            && let Ok(s) = String::from_utf8(buf)
+        {
+            logger.log(&s).await;
+        }
     }
 }
 
-pub async fn handle_repl_command(command: &str, logger: &Logger) -> (Routine, Vec<String>) {
+/// Launches the routine named by `/name args...` from the registry. Any other
+/// line is sent as a raw client message if it parses as one.
+pub async fn handle_repl_command(
+    command: &str,
+    scheduler: &mut Scheduler,
+    registry: &Registry,
+    ctx: &Context,
+) -> Vec<ClientMessage> {
+    let logger = &ctx.logger;
     logger
         .log(&format!("[REPL  ]: handling repl command '{}'\n", command))
         .await;
 
-    match command {
-        "/start" => (Routine::StartGame, vec![]),
-        "/seeded" => (Routine::StartSeededGame, vec![]),
-        _ => {
-            logger
-                .log(&format!("unknown repl command: {}\n", command))
-                .await;
-            (Routine::Idle, vec![command.to_string()])
+    if command == "/routines" {
+        for entry in registry.entries() {
+            logger.log(&format!("[REPL  ]: {}\n", entry.usage)).await;
         }
-    }
-}
-
-mod command {
-    use chrono::Local;
-    use serde_json::json;
-
-    pub fn register_random() -> String {
-        let now = Local::now();
-        let username = format!("dirkle{}", now.format("%Y%m%d%H%M%S"));
-        json!({
-            "msg": "register",
-            "username": username,
-            "password": "aaa",
-            "email": ""
-        })
-        .to_string()
+        return vec![];
     }
 
-    pub fn play() -> String {
-        json!({"msg":"play","game_id":"dcss-web-trunk"}).to_string()
+    if command == "/messages" {
+        for entry in ctx.message_log.lock().await.entries() {
+            logger
+                .log(&format!(
+                    "[REPL  ]: {} {:?}: {}\n",
+                    entry.turn,
+                    entry.channel,
+                    entry.text()
+                ))
+                .await;
+        }
+        return vec![];
     }
 
-    pub fn play_seeded() -> String {
-        json!({"msg":"play","game_id":"seeded-web-trunk"}).to_string()
-    }
+    if let Some(line) = command.strip_prefix('/') {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
 
-    pub fn send_text(text: &str) -> String {
-        json!({"msg": "input", "text": text}).to_string()
-    }
-
-    pub fn send_keycode(keycode: i32) -> String {
-        json!({"msg":"key","keycode": keycode}).to_string()
-    }
+        let Some(entry) = registry.get(name) else {
+            logger
+                .log(&format!("unknown routine: {} (try /routines)\n", name))
+                .await;
+            return vec![];
+        };
 
-    #[allow(dead_code)]
-    pub fn register() -> String {
-        json!({
-            "msg": "register",
-            "username": "dirkle",
-            "password": "aaa",
-            "email": ""
-        })
-        .to_string()
+        return match entry.build(&args) {
+            Ok(routine) => match entry.launch {
+                Launch::Replace => scheduler.start(routine).await,
+                Launch::Push => scheduler.push(routine).await,
+            },
+            Err(e) => {
+                logger.log(&format!("{}: {}\n", entry.name, e)).await;
+                vec![]
+            }
+        };
     }
 
-    #[allow(dead_code)]
-    pub fn login() -> String {
-        json!({"msg":"login","username":"dirkle","password":"aaa"}).to_string()
+    match serde_json::from_str::<ClientMessage>(command) {
+        Ok(msg) => vec![msg],
+        Err(e) => {
+            logger
+                .log(&format!("unknown repl command: {} ({})\n", command, e))
+                .await;
+            vec![]
+        }
     }
 }
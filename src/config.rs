@@ -0,0 +1,133 @@
+use crate::account::Credentials;
+use clap::Parser;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Read when no `--config` is given and the file exists.
+const DEFAULT_CONFIG_FILE: &str = "crawlbot.toml";
+
+const DEFAULT_URL: &str = "ws://127.0.0.1:8080/socket";
+const DEFAULT_GAME_ID: &str = "dcss-web-trunk";
+const DEFAULT_SEEDED_GAME_ID: &str = "seeded-web-trunk";
+const DEFAULT_LOG_DIR: &str = "./logs";
+
+/// Command line flags. Each one can also be set through its environment
+/// variable, and falls back to the config file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Plays Dungeon Crawl Stone Soup over webtiles")]
+struct Cli {
+    /// TOML config file [default: crawlbot.toml if present]
+    #[arg(short, long, env = "CRAWLBOT_CONFIG")]
+    config: Option<PathBuf>,
+    /// Webtiles socket, ws:// or wss://
+    #[arg(long, env = "CRAWLBOT_URL")]
+    url: Option<String>,
+    /// Origin header sent with the websocket handshake
+    #[arg(long, env = "CRAWLBOT_ORIGIN")]
+    origin: Option<String>,
+    /// Game id for unseeded games
+    #[arg(long, env = "CRAWLBOT_GAME_ID")]
+    game_id: Option<String>,
+    /// Game id for seeded games
+    #[arg(long, env = "CRAWLBOT_SEEDED_GAME_ID")]
+    seeded_game_id: Option<String>,
+    /// Account to log in with, registered if the login is refused
+    /// [default: a fresh dirkle<timestamp> account]
+    #[arg(long, env = "CRAWLBOT_USERNAME")]
+    username: Option<String>,
+    /// Password of the account [default: aaa]
+    #[arg(long, env = "CRAWLBOT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// E-mail given when registering
+    #[arg(long, env = "CRAWLBOT_EMAIL")]
+    email: Option<String>,
+    /// Cap on keys sent per second, e.g. for shared public servers
+    /// [default: as fast as the game answers]
+    #[arg(long, env = "CRAWLBOT_MAX_ACTIONS_PER_SECOND")]
+    max_actions_per_second: Option<f64>,
+    /// Where logs and sweep summaries are written
+    #[arg(long, env = "CRAWLBOT_LOG_DIR")]
+    log_dir: Option<PathBuf>,
+    /// Routine launched once connected, as typed on the REPL without the
+    /// slash, e.g. "start MiFi"
+    #[arg(long, env = "CRAWLBOT_ROUTINE")]
+    routine: Option<String>,
+}
+
+/// The same settings as [`Cli`], all optional, as read from the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    url: Option<String>,
+    origin: Option<String>,
+    game_id: Option<String>,
+    seeded_game_id: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    email: Option<String>,
+    max_actions_per_second: Option<f64>,
+    log_dir: Option<PathBuf>,
+    routine: Option<String>,
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("cannot parse {}: {}", path.display(), e))
+    }
+}
+
+/// Settings for one run, from flags, environment and config file in that
+/// order of precedence.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub url: String,
+    pub origin: Option<String>,
+    pub game_id: String,
+    pub seeded_game_id: String,
+    pub credentials: Credentials,
+    pub max_actions_per_second: Option<f64>,
+    pub log_dir: PathBuf,
+    pub routine: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        let cli = Cli::parse();
+        let file = match &cli.config {
+            Some(path) => FileConfig::load(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                FileConfig::load(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => FileConfig::default(),
+        };
+
+        Ok(Self {
+            url: cli
+                .url
+                .or(file.url)
+                .unwrap_or_else(|| DEFAULT_URL.to_string()),
+            origin: cli.origin.or(file.origin),
+            game_id: cli
+                .game_id
+                .or(file.game_id)
+                .unwrap_or_else(|| DEFAULT_GAME_ID.to_string()),
+            seeded_game_id: cli
+                .seeded_game_id
+                .or(file.seeded_game_id)
+                .unwrap_or_else(|| DEFAULT_SEEDED_GAME_ID.to_string()),
+            credentials: Credentials::new(
+                cli.username.or(file.username),
+                cli.password.or(file.password),
+                cli.email.or(file.email),
+            ),
+            max_actions_per_second: cli.max_actions_per_second.or(file.max_actions_per_second),
+            log_dir: cli
+                .log_dir
+                .or(file.log_dir)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_LOG_DIR)),
+            routine: cli.routine.or(file.routine),
+        })
+    }
+}
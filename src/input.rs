@@ -0,0 +1,161 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use std::time::{Duration, Instant};
+
+/// After this long without the game starting to process our input, the keys
+/// are assumed to have been swallowed, e.g. by an unknown command.
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the game is waiting for, from the `mode` of `input_mode` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// No `input_mode` seen yet on this connection.
+    Unknown,
+    /// Mode 0: processing input, not accepting any.
+    Busy,
+    /// Mode 1: waiting for the next command.
+    Command,
+    /// Modes 2-4: picking a target or direction.
+    Target,
+    /// Mode 5: a `--more--` has to be dismissed.
+    More,
+    /// Mode 7: a line of text is asked for.
+    Prompt,
+    /// Mode 8: a yes/no question.
+    YesNo,
+    Other(i32),
+}
+
+impl InputMode {
+    pub fn from_code(mode: i32) -> Self {
+        match mode {
+            0 => InputMode::Busy,
+            1 => InputMode::Command,
+            2..=4 => InputMode::Target,
+            5 => InputMode::More,
+            7 => InputMode::Prompt,
+            8 => InputMode::YesNo,
+            other => InputMode::Other(other),
+        }
+    }
+}
+
+/// Tracks the input mode and the game input still in flight. Every key sent
+/// outside a popup counts as outstanding until the game reports it busy with
+/// it, so a stale "waiting for a command" that was queued before the keys
+/// went out is not mistaken for their answer.
+#[derive(Debug, Clone)]
+pub struct InputState {
+    mode: InputMode,
+    outstanding: usize,
+    last_sent: Option<Instant>,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            mode: InputMode::Unknown,
+            outstanding: 0,
+            last_sent: None,
+        }
+    }
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, msg: &ServerMessage) {
+        let ServerMessage::InputMode(input) = msg else {
+            return;
+        };
+        self.mode = InputMode::from_code(input.mode);
+        if self.mode == InputMode::Busy {
+            self.outstanding = self.outstanding.saturating_sub(1);
+        } else if self
+            .last_sent
+            .is_some_and(|sent| sent.elapsed() >= ACK_TIMEOUT)
+        {
+            self.outstanding = 0;
+        }
+    }
+
+    /// Records a message on its way to the server. Keys answering a popup
+    /// close it rather than run a command, so they are not waited for.
+    pub fn sent(&mut self, msg: &ClientMessage, popup_open: bool) {
+        if msg.is_action() && !popup_open {
+            self.outstanding += 1;
+            self.last_sent = Some(Instant::now());
+        }
+    }
+
+    /// Forgets everything, e.g. after the connection dropped.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
+    /// Whether the game waits for a command and everything sent before has
+    /// been processed.
+    pub fn awaiting_command(&self) -> bool {
+        self.mode == InputMode::Command && self.outstanding == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parse_messages;
+    use std::fs;
+
+    fn move_capture() -> Vec<ServerMessage> {
+        let raw = fs::read_to_string("test/research/move/01-msgs.json").unwrap();
+        parse_messages(serde_json::from_str(&raw).unwrap())
+    }
+
+    fn mode(code: i32) -> ServerMessage {
+        serde_json::from_value(serde_json::json!({"msg": "input_mode", "mode": code})).unwrap()
+    }
+
+    #[test]
+    fn move_capture_acknowledges_one_key() {
+        let mut state = InputState::new();
+        state.sent(&ClientMessage::input("l"), false);
+        assert!(!state.awaiting_command());
+
+        for msg in move_capture() {
+            state.update(&msg);
+        }
+        assert_eq!(state.mode(), InputMode::Command);
+        assert!(state.awaiting_command());
+    }
+
+    #[test]
+    fn stale_command_mode_does_not_release_queued_keys() {
+        let mut state = InputState::new();
+        state.update(&mode(1));
+        state.sent(&ClientMessage::input("l"), false);
+        state.sent(&ClientMessage::input("l"), false);
+
+        state.update(&mode(1));
+        assert!(!state.awaiting_command());
+        state.update(&mode(0));
+        state.update(&mode(1));
+        assert!(!state.awaiting_command());
+        state.update(&mode(0));
+        state.update(&mode(1));
+        assert!(state.awaiting_command());
+    }
+
+    #[test]
+    fn popup_answers_and_control_messages_are_not_waited_for() {
+        let mut state = InputState::new();
+        state.update(&mode(1));
+        state.sent(&ClientMessage::key(27), true);
+        state.sent(&ClientMessage::Pong, false);
+        assert!(state.awaiting_command());
+    }
+}
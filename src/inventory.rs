@@ -0,0 +1,267 @@
+use crate::protocol::ItemMessage;
+use std::collections::BTreeMap;
+
+/// `ISFLAG_IDENTIFIED` from crawl's item-prop-enum.h, set once everything
+/// about the item is known.
+const ISFLAG_IDENTIFIED: u64 = 0x0000_0001;
+
+/// `object_class_type` from crawl's item-prop-enum.h.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemClass {
+    Weapon,
+    Missile,
+    Armour,
+    Wand,
+    Food,
+    Scroll,
+    Jewellery,
+    Potion,
+    Book,
+    Staff,
+    Orb,
+    Miscellany,
+    Corpse,
+    Gold,
+    Rod,
+    Rune,
+    Talisman,
+    Gem,
+    /// Only known from the map glyph, never sent as an inventory base type.
+    Bauble,
+    Unassigned,
+    Other(i32),
+}
+
+impl ItemClass {
+    pub fn from_base_type(base_type: i32) -> Self {
+        match base_type {
+            0 => ItemClass::Weapon,
+            1 => ItemClass::Missile,
+            2 => ItemClass::Armour,
+            3 => ItemClass::Wand,
+            4 => ItemClass::Food,
+            5 => ItemClass::Scroll,
+            6 => ItemClass::Jewellery,
+            7 => ItemClass::Potion,
+            8 => ItemClass::Book,
+            9 => ItemClass::Staff,
+            10 => ItemClass::Orb,
+            11 => ItemClass::Miscellany,
+            12 => ItemClass::Corpse,
+            13 => ItemClass::Gold,
+            14 => ItemClass::Rod,
+            15 => ItemClass::Rune,
+            16 => ItemClass::Talisman,
+            17 => ItemClass::Gem,
+            100 => ItemClass::Unassigned,
+            other => ItemClass::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub slot: usize,
+    pub class: ItemClass,
+    pub sub_type: i32,
+    pub quantity: i32,
+    pub plus: i32,
+    pub plus2: i32,
+    pub flags: u64,
+    pub name: String,
+    pub inscription: String,
+    pub useless: bool,
+}
+
+impl Item {
+    fn empty(slot: usize) -> Self {
+        Self {
+            slot,
+            class: ItemClass::Unassigned,
+            sub_type: 0,
+            quantity: 0,
+            plus: 0,
+            plus2: 0,
+            flags: 0,
+            name: String::new(),
+            inscription: String::new(),
+            useless: false,
+        }
+    }
+
+    fn update(&mut self, msg: &ItemMessage) {
+        if let Some(base_type) = msg.base_type {
+            self.class = ItemClass::from_base_type(base_type);
+        }
+        if let Some(sub_type) = msg.sub_type {
+            self.sub_type = sub_type;
+        }
+        if let Some(quantity) = msg.quantity {
+            self.quantity = quantity;
+        }
+        if let Some(plus) = msg.plus {
+            self.plus = plus;
+        }
+        if let Some(plus2) = msg.plus2 {
+            self.plus2 = plus2;
+        }
+        if let Some(flags) = msg.flags {
+            self.flags = flags;
+        }
+        if let Some(name) = &msg.name {
+            self.name = name.clone();
+        }
+        if let Some(inscription) = &msg.inscription {
+            self.inscription = inscription.clone();
+        }
+        if let Some(useless) = msg.useless {
+            self.useless = useless != 0;
+        }
+    }
+
+    /// Inventory letter used to select this item in game menus.
+    pub fn letter(&self) -> char {
+        slot_letter(self.slot)
+    }
+
+    pub fn is_identified(&self) -> bool {
+        self.flags & ISFLAG_IDENTIFIED != 0
+    }
+}
+
+pub fn slot_letter(slot: usize) -> char {
+    match slot {
+        0..=25 => (b'a' + slot as u8) as char,
+        _ => (b'A' + (slot - 26) as u8) as char,
+    }
+}
+
+/// The player's inventory, built from the slot-keyed `inv` object of `player`
+/// messages. Slots the server reports as unassigned or with zero quantity are
+/// removed.
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    items: BTreeMap<usize, Item>,
+}
+
+impl Inventory {
+    pub fn update(&mut self, inv: &BTreeMap<String, ItemMessage>) {
+        for (key, msg) in inv {
+            let Ok(slot) = key.parse::<usize>() else {
+                continue;
+            };
+            let item = self.items.entry(slot).or_insert_with(|| Item::empty(slot));
+            item.update(msg);
+
+            if item.class == ItemClass::Unassigned || item.quantity <= 0 {
+                self.items.remove(&slot);
+            }
+        }
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.values()
+    }
+
+    pub fn get(&self, letter: char) -> Option<&Item> {
+        self.items().find(|item| item.letter() == letter)
+    }
+
+    pub fn of_class(&self, class: ItemClass) -> impl Iterator<Item = &Item> {
+        self.items().filter(move |item| item.class == class)
+    }
+
+    /// Slot letter of the first item of `class` whose type is still unknown,
+    /// e.g. the next potion to quaff-identify.
+    pub fn first_unidentified(&self, class: ItemClass) -> Option<char> {
+        self.of_class(class)
+            .find(|item| !item.is_identified())
+            .map(Item::letter)
+    }
+
+    /// Slot letter of the first item whose name contains `name`.
+    pub fn find_by_name(&self, name: &str) -> Option<char> {
+        self.items()
+            .find(|item| item.name.contains(name))
+            .map(Item::letter)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ServerMessage, parse_messages};
+
+    fn inv(value: serde_json::Value) -> BTreeMap<String, ItemMessage> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn finds_the_first_unidentified_potion() {
+        let mut inventory = Inventory::default();
+        inventory.update(&inv(serde_json::json!({
+            "0": {"base_type": 0, "quantity": 1, "flags": 0, "name": "club"},
+            "1": {"base_type": 7, "quantity": 2, "flags": 1, "name": "2 potions of curing"},
+            "2": {"base_type": 7, "quantity": 1, "flags": 0, "name": "bubbling potion"},
+            "3": {"base_type": 5, "quantity": 1, "flags": 0, "name": "scroll labelled XOB"},
+        })));
+
+        assert_eq!(inventory.first_unidentified(ItemClass::Potion), Some('c'));
+        assert_eq!(inventory.first_unidentified(ItemClass::Scroll), Some('d'));
+        assert_eq!(inventory.first_unidentified(ItemClass::Wand), None);
+        assert_eq!(inventory.of_class(ItemClass::Potion).count(), 2);
+        assert_eq!(inventory.get('b').unwrap().quantity, 2);
+        assert_eq!(inventory.find_by_name("of curing"), Some('b'));
+
+        // Quaffing identifies the potion; the partial update only has flags.
+        inventory.update(&inv(serde_json::json!({
+            "2": {"flags": 1, "name": "potion of might"},
+        })));
+        assert_eq!(inventory.first_unidentified(ItemClass::Potion), None);
+        assert_eq!(inventory.get('c').unwrap().quantity, 1);
+    }
+
+    #[test]
+    fn empty_and_unassigned_slots_are_removed() {
+        let mut inventory = Inventory::default();
+        inventory.update(&inv(serde_json::json!({
+            "0": {"base_type": 0, "quantity": 1, "name": "club"},
+            "1": {"base_type": 7, "quantity": 1, "name": "bubbling potion"},
+        })));
+        assert_eq!(inventory.len(), 2);
+
+        inventory.update(&inv(serde_json::json!({"1": {"quantity": 0}})));
+        assert_eq!(inventory.len(), 1);
+        assert!(inventory.get('b').is_none());
+
+        inventory.update(&inv(
+            serde_json::json!({"0": {"base_type": 100, "quantity": 0}}),
+        ));
+        assert_eq!(inventory.len(), 0);
+    }
+
+    #[test]
+    fn login_capture_starts_with_identified_armour() {
+        let raw = std::fs::read_to_string("test/research/login/07-player.json").unwrap();
+        let mut inventory = Inventory::default();
+        for msg in parse_messages(serde_json::from_str(&raw).unwrap()) {
+            if let ServerMessage::Player(player) = msg
+                && let Some(inv) = &player.inv
+            {
+                inventory.update(inv);
+            }
+        }
+
+        assert_eq!(inventory.len(), 1);
+        let skin = inventory.items().next().unwrap();
+        assert_eq!(skin.letter(), 'a');
+        assert_eq!(skin.class, ItemClass::Armour);
+        assert_eq!(skin.name, "+0 animal skin");
+        assert_eq!(skin.flags, 0x2000_0001);
+        assert!(skin.is_identified());
+    }
+}
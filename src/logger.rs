@@ -6,13 +6,15 @@ use std::path::Path;
 
 #[derive(Clone)]
 pub struct Logger {
-    stdout: SharedWriter,
-    file: std::sync::Arc<tokio::sync::Mutex<File>>,
+    stdout: Option<SharedWriter>,
+    file: Option<std::sync::Arc<tokio::sync::Mutex<File>>>,
 }
 
 impl Logger {
-    pub async fn new(stdout: SharedWriter) -> Result<Self, Box<dyn std::error::Error>> {
-        let logs_dir = Path::new("./logs");
+    pub async fn new(
+        stdout: SharedWriter,
+        logs_dir: &Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         if !logs_dir.exists() {
             fs::create_dir_all(logs_dir)?;
         }
@@ -23,23 +25,36 @@ impl Logger {
         let file = File::create(file_path)?;
 
         Ok(Self {
-            stdout,
-            file: std::sync::Arc::new(tokio::sync::Mutex::new(file)),
+            stdout: Some(stdout),
+            file: Some(std::sync::Arc::new(tokio::sync::Mutex::new(file))),
         })
     }
 
+    /// A logger that drops everything, for tests that run without a REPL.
+    #[cfg(test)]
+    pub fn silent() -> Self {
+        Self {
+            stdout: None,
+            file: None,
+        }
+    }
+
     pub async fn log(&self, message: &str) {
         let now = Local::now();
         let timestamped_message = format!("{} {}", now.format("%Y-%m-%dT%H:%M:%S"), message);
 
         // Write to stdout (SharedWriter)
-        let mut stdout = self.stdout.clone();
-        let _ = stdout.write_all(timestamped_message.as_bytes());
-        let _ = stdout.flush();
+        if let Some(stdout) = &self.stdout {
+            let mut stdout = stdout.clone();
+            let _ = stdout.write_all(timestamped_message.as_bytes());
+            let _ = stdout.flush();
+        }
 
         // Write to file
-        let mut file = self.file.lock().await;
-        let _ = file.write_all(timestamped_message.as_bytes());
-        let _ = file.flush();
+        if let Some(file) = &self.file {
+            let mut file = file.lock().await;
+            let _ = file.write_all(timestamped_message.as_bytes());
+            let _ = file.flush();
+        }
     }
 }
@@ -1,121 +1,363 @@
+mod account;
 mod commands;
+mod config;
+mod input;
+mod inventory;
 mod logger;
 mod map;
+mod messages;
+mod pathfinding;
+mod player;
 mod protocol;
-
-use crate::protocol::normalize_messages;
-use commands::Routine;
+mod routines;
+mod scheduler;
+mod throttle;
+mod ui;
+
+use crate::account::Account;
+use crate::config::Config;
+use crate::protocol::{ClientMessage, ServerMessage, parse_messages};
 use flate2::{Decompress, FlushDecompress};
 use futures_util::SinkExt;
 use futures_util::StreamExt;
+use input::InputState;
 use logger::Logger;
 use map::MapState;
+use messages::MessageLog;
+use player::PlayerState;
+use routines::Context;
+use routines::reconnect::Reconnect;
 use rustyline_async::{Readline, ReadlineEvent};
+use scheduler::Scheduler;
 use serde_json::Value;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, mpsc};
-use tokio::time::sleep;
+use std::time::{Duration, Instant};
+use throttle::{Throttle, signals_ready};
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
 use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_util::sync::CancellationToken;
+use ui::UiStack;
 
-type WsSender = futures_util::stream::SplitSink<
-    WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    Message,
->;
-type WsReceiver = futures_util::stream::SplitStream<
-    WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
->;
+type WsStream = WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+type WsSender = futures_util::stream::SplitSink<WsStream, Message>;
+type WsReceiver = futures_util::stream::SplitStream<WsStream>;
 
-const SENDER_DELAY_MS: u64 = 100;
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+/// How long the tasks get to wind down after the REPL exits.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let url_str = "ws://127.0.0.1:8080/socket";
-    let (ws_stream, _) = connect_async(url_str).await?;
+    let config = Arc::new(Config::load()?);
+
+    let ws_stream = connect(&config).await?;
 
-    let (ws_sender, ws_receiver) = ws_stream.split();
     let map_state = Arc::new(Mutex::new(MapState::new()));
-    let current_routine = Arc::new(Mutex::new(Routine::Init));
+    let player_state = Arc::new(Mutex::new(PlayerState::new()));
+    let message_log = Arc::new(Mutex::new(MessageLog::new()));
 
     let (rl, stdout) = Readline::new("DCSS    > ".to_string())?;
 
-    let logger = Logger::new(stdout).await?;
+    let logger = Logger::new(stdout, &config.log_dir).await?;
     logger
-        .log("Connected. Forcing Manual Decompression...\n")
+        .log(&format!(
+            "Connected to {}. Forcing Manual Decompression...\n",
+            config.url
+        ))
         .await;
 
-    // Channel for sending messages to the WebSocket
-    let (tx_sender, rx_sender) = mpsc::channel::<Message>(32);
+    // Channel for sending messages to the WebSocket, in the batches the
+    // routines produced them
+    let (tx_sender, rx_sender) = mpsc::channel::<Vec<ClientMessage>>(32);
+    // Channel for control messages like pong, which skip the pacing
+    let (tx_control, rx_control) = mpsc::channel::<ClientMessage>(32);
     // Channel for incoming messages (Server + Repl)
     let (tx_receiver, rx_receiver) = mpsc::channel::<protocol::ProcessMessage>(32);
 
-    spawn_sender(ws_sender, rx_sender, logger.clone());
-    spawn_receiver(ws_receiver, tx_receiver.clone(), logger.clone());
-
-    spawn_processor(
-        rx_receiver,
-        tx_sender,
-        Arc::clone(&map_state),
-        Arc::clone(&current_routine),
+    let shutdown = CancellationToken::new();
+    let connection = spawn_connection(
+        ws_stream,
+        config.clone(),
+        rx_sender,
+        rx_control,
+        tx_receiver.clone(),
+        shutdown.clone(),
         logger.clone(),
     );
 
-    run_repl(rl, logger, tx_receiver, current_routine).await?;
+    let ctx = Context {
+        map_state,
+        player_state,
+        message_log,
+        ui_stack: Arc::new(Mutex::new(UiStack::new())),
+        input: Arc::new(Mutex::new(InputState::new())),
+        account: Arc::new(Mutex::new(Account::new(config.credentials.clone()))),
+        config: config.clone(),
+        logger: logger.clone(),
+    };
+    let processor = spawn_processor(rx_receiver, tx_sender, tx_control, shutdown.clone(), ctx);
+
+    if let Some(routine) = &config.routine {
+        tx_receiver
+            .send(protocol::ProcessMessage::Repl(format!("/{}", routine)))
+            .await?;
+    }
 
-    Ok(())
+    let result = run_repl(rl, logger.clone(), tx_receiver).await;
+
+    logger.log("Shutting down...\n").await;
+    shutdown.cancel();
+    if timeout(SHUTDOWN_TIMEOUT, async {
+        let _ = tokio::join!(processor, connection);
+    })
+    .await
+    .is_err()
+    {
+        logger.log("Shutdown timed out\n").await;
+    }
+
+    result
 }
 
-fn spawn_sender(mut ws_sender: WsSender, mut rx: mpsc::Receiver<Message>, logger: Logger) {
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            sleep(Duration::from_millis(SENDER_DELAY_MS)).await;
-            logger.log(&format!("[CLIENT]: {}\n", msg)).await;
-            if let Err(e) = ws_sender.send(msg).await {
-                eprintln!("WebSocket send error: {:?}", e);
-                break;
-            }
-        }
-    });
+async fn connect(config: &Config) -> Result<WsStream, tungstenite::Error> {
+    let mut request = config.url.as_str().into_client_request()?;
+    if let Some(origin) = &config.origin {
+        let origin =
+            HeaderValue::from_str(origin).map_err(|e| tungstenite::Error::HttpFormat(e.into()))?;
+        request.headers_mut().insert("Origin", origin);
+    }
+    let (ws_stream, _) = connect_async(request).await?;
+    Ok(ws_stream)
 }
 
-fn spawn_receiver(
-    mut ws_receiver: WsReceiver,
+/// Runs the websocket and replaces it whenever it drops, backing off
+/// exponentially while the server stays unreachable. The processor is told
+/// about both so it can log in again and resume the game. Ends once the
+/// processor has shut down and everything it queued is sent.
+fn spawn_connection(
+    mut ws_stream: WsStream,
+    config: Arc<Config>,
+    mut rx_sender: mpsc::Receiver<Vec<ClientMessage>>,
+    mut rx_control: mpsc::Receiver<ClientMessage>,
     tx_receiver: mpsc::Sender<protocol::ProcessMessage>,
+    shutdown: CancellationToken,
     logger: Logger,
-) {
+) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let mut buffer = Vec::new();
-        let sync_buffer = [0x00, 0x00, 0xff, 0xff];
-        let mut decompressor = Decompress::new(false); // raw deflate
-
-        while let Some(msg) = ws_receiver.next().await {
-            match msg {
-                Ok(Message::Binary(data)) => {
-                    let res = handle_binary_message(
-                        data.to_vec(),
-                        &sync_buffer,
-                        &mut decompressor,
-                        &mut buffer,
-                        &tx_receiver,
-                        &logger,
-                    )
+        let (tx_ready, rx_ready) = watch::channel(0);
+        let mut throttle = Throttle::new(rx_ready, config.max_actions_per_second);
+        let mut backoff = RECONNECT_MIN_DELAY;
+        loop {
+            let connected_at = Instant::now();
+            let (ws_sender, ws_receiver) = ws_stream.split();
+            let dropped = tokio::select! {
+                dropped = run_sender(ws_sender, &mut rx_sender, &mut rx_control, &mut throttle, &logger) => dropped,
+                dropped = run_receiver(ws_receiver, &tx_receiver, &tx_ready, &logger) => dropped,
+            };
+            // Our own channels closed: the bot is exiting.
+            let Some(reason) = dropped else {
+                return;
+            };
+
+            logger.log(&format!("Disconnected: {}\n", reason)).await;
+            if shutdown.is_cancelled() {
+                return;
+            }
+            if tx_receiver
+                .send(protocol::ProcessMessage::Disconnected)
+                .await
+                .is_err()
+            {
+                return;
+            }
+
+            // A connection that held for a while starts over with short waits.
+            if connected_at.elapsed() >= RECONNECT_MAX_DELAY {
+                backoff = RECONNECT_MIN_DELAY;
+            }
+            ws_stream = loop {
+                logger
+                    .log(&format!(
+                        "Reconnecting to {} in {}s...\n",
+                        config.url,
+                        backoff.as_secs()
+                    ))
                     .await;
+                tokio::select! {
+                    _ = sleep(backoff) => {}
+                    _ = shutdown.cancelled() => return,
+                }
+                backoff = (backoff * 2).min(RECONNECT_MAX_DELAY);
+                match connect(&config).await {
+                    Ok(ws_stream) => break ws_stream,
+                    Err(e) => logger.log(&format!("Reconnect failed: {}\n", e)).await,
+                }
+            };
+
+            // Input queued for the old session would land before the login.
+            let mut stale = 0;
+            while let Ok(batch) = rx_sender.try_recv() {
+                stale += batch.len();
+            }
+            while rx_control.try_recv().is_ok() {
+                stale += 1;
+            }
+            logger
+                .log(&format!("Reconnected, dropped {} queued messages\n", stale))
+                .await;
+            if tx_receiver
+                .send(protocol::ProcessMessage::Reconnected)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+}
+
+/// Sends queued client messages until the socket fails, returning why, or
+/// closes the socket and returns `None` once the game input queue is closed.
+/// Game input is paced by the throttle; control messages have their own queue
+/// and go out right away, even while a batch is waiting.
+async fn run_sender(
+    ws_sender: WsSender,
+    rx: &mut mpsc::Receiver<Vec<ClientMessage>>,
+    control: &mut mpsc::Receiver<ClientMessage>,
+    throttle: &mut Throttle,
+    logger: &Logger,
+) -> Option<String> {
+    let ws_sender = Mutex::new(ws_sender);
 
-                    if let Err(e) = res {
-                        let err_msg = format!("Error handling message: {:?}\n", e);
-                        logger.log(&err_msg).await;
+    let control_loop = async {
+        while let Some(msg) = control.recv().await {
+            if let Err(e) = send_message(&ws_sender, msg, logger).await {
+                return Some(e);
+            }
+        }
+        // The game input queue closes at the same time and decides when to
+        // stop.
+        std::future::pending().await
+    };
+
+    let game_loop = async {
+        while let Some(batch) = rx.recv().await {
+            // The game buffers keys, so only the first action of a batch waits
+            // for it to catch up with the previous one.
+            let mut first_action = true;
+            for msg in batch {
+                if msg.is_action() && msg.validate().is_ok() {
+                    let ready = if first_action {
+                        throttle.action().await
+                    } else {
+                        throttle.follow_up().await;
+                        true
+                    };
+                    first_action = false;
+                    if !ready {
+                        logger
+                            .log("[CLIENT]: game did not signal readiness, sending anyway\n")
+                            .await;
                     }
                 }
-                Ok(Message::Close(_)) => break,
-                Err(e) => {
-                    logger.log(&format!("WebSocket error: {:?}\n", e)).await;
-                    break;
+                if let Err(e) = send_message(&ws_sender, msg, logger).await {
+                    return Some(e);
                 }
-                _ => {}
             }
         }
-    });
+        None
+    };
+
+    let dropped = tokio::select! {
+        dropped = control_loop => dropped,
+        dropped = game_loop => dropped,
+    };
+    if dropped.is_none() {
+        let _ = ws_sender.into_inner().close().await;
+    }
+    dropped
+}
+
+/// Validates and sends a single message; invalid ones are logged and dropped.
+async fn send_message(
+    ws_sender: &Mutex<WsSender>,
+    msg: ClientMessage,
+    logger: &Logger,
+) -> Result<(), String> {
+    if let Err(e) = msg.validate() {
+        logger
+            .log(&format!(
+                "[CLIENT]: dropping invalid {}: {}\n",
+                msg.kind(),
+                e
+            ))
+            .await;
+        return Ok(());
+    }
+
+    let json = msg.to_json();
+    logger.log(&format!("[CLIENT]: {}\n", json)).await;
+    ws_sender
+        .lock()
+        .await
+        .send(Message::Text(json.into()))
+        .await
+        .map_err(|e| format!("send failed: {}", e))
+}
+
+/// Forwards server messages to the processor until the socket closes,
+/// returning why, or `None` once the processor is gone. Every connection
+/// starts a new deflate stream, so the decompressor lives only this long.
+async fn run_receiver(
+    mut ws_receiver: WsReceiver,
+    tx_receiver: &mpsc::Sender<protocol::ProcessMessage>,
+    ready: &watch::Sender<u64>,
+    logger: &Logger,
+) -> Option<String> {
+    let mut buffer = Vec::new();
+    let sync_buffer = [0x00, 0x00, 0xff, 0xff];
+    let mut decompressor = Decompress::new(false); // raw deflate
+
+    while let Some(msg) = ws_receiver.next().await {
+        match msg {
+            Ok(Message::Binary(data)) => {
+                let res = handle_binary_message(
+                    data.to_vec(),
+                    &sync_buffer,
+                    &mut decompressor,
+                    &mut buffer,
+                    tx_receiver,
+                    ready,
+                    logger,
+                )
+                .await;
+
+                if let Err(e) = res {
+                    if tx_receiver.is_closed() {
+                        return None;
+                    }
+                    let err_msg = format!("Error handling message: {:?}\n", e);
+                    logger.log(&err_msg).await;
+                }
+            }
+            Ok(Message::Close(frame)) => {
+                return Some(match frame {
+                    Some(frame) => format!("closed by server: {}", frame),
+                    None => "closed by server".to_string(),
+                });
+            }
+            Err(e) => return Some(format!("websocket error: {}", e)),
+            _ => {}
+        }
+    }
+    Some("stream ended".to_string())
 }
 
 async fn handle_binary_message(
@@ -124,6 +366,7 @@ async fn handle_binary_message(
     decompressor: &mut Decompress,
     buffer: &mut Vec<u8>,
     tx_receiver: &mpsc::Sender<protocol::ProcessMessage>,
+    ready: &watch::Sender<u64>,
     logger: &Logger,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut input = data;
@@ -167,9 +410,12 @@ async fn handle_binary_message(
             last_offset = stream.byte_offset();
             logger.log(&format!("[SERVER]: {}\n", value)).await;
 
-            for msg_val in normalize_messages(value) {
+            for msg in parse_messages(value) {
+                if signals_ready(&msg) {
+                    ready.send_modify(|count| *count += 1);
+                }
                 tx_receiver
-                    .send(protocol::ProcessMessage::Server(msg_val))
+                    .send(protocol::ProcessMessage::Server(msg))
                     .await?;
             }
         }
@@ -184,96 +430,76 @@ async fn handle_binary_message(
 
 fn spawn_processor(
     mut rx_receiver: mpsc::Receiver<protocol::ProcessMessage>,
-    tx_sender: mpsc::Sender<Message>,
-    map_state: Arc<Mutex<MapState>>,
-    current_routine: Arc<Mutex<Routine>>,
-    logger: Logger,
-) {
+    tx_sender: mpsc::Sender<Vec<ClientMessage>>,
+    tx_control: mpsc::Sender<ClientMessage>,
+    shutdown: CancellationToken,
+    ctx: Context,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let mut peeked: Option<protocol::ProcessMessage> = None;
-
-        loop {
-            let msg = if let Some(m) = peeked.take() {
-                Some(m)
-            } else {
-                rx_receiver.recv().await
-            };
-
-            let Some(msg) = msg else { break };
-
-            match msg {
+        let mut scheduler = Scheduler::new(ctx.clone());
+        let registry = routines::builtin();
+
+        while let Some(msg) = tokio::select! {
+            msg = rx_receiver.recv() => msg,
+            _ = shutdown.cancelled() => None,
+        } {
+            let outgoing = match msg {
                 protocol::ProcessMessage::Repl(line) => {
-                    let mut routine = current_routine.lock().await;
-                    let (new_routine, outgoing) =
-                        commands::handle_repl_command(&line, &logger).await;
-                    *routine = new_routine;
-
-                    let mut messages = outgoing;
-                    if messages.is_empty() {
-                        let (new_state, new_messages) = commands::execute_routine(
-                            routine.clone(),
-                            None,
-                            None,
-                            &map_state,
-                            &logger,
-                        )
-                        .await;
-                        *routine = new_state;
-                        messages = new_messages;
-                    }
-
-                    for msg_str in messages {
-                        let _ = tx_sender.send(Message::Text(msg_str.into())).await;
-                    }
+                    commands::handle_repl_command(&line, &mut scheduler, &registry, &ctx).await
+                }
+                protocol::ProcessMessage::Disconnected => {
+                    ctx.account.lock().await.disconnected();
+                    ctx.ui_stack.lock().await.clear();
+                    ctx.input.lock().await.reset();
+                    // The game resends the map and player once back in it.
+                    *ctx.map_state.lock().await = MapState::new();
+                    *ctx.player_state.lock().await = PlayerState::new();
+                    continue;
                 }
-                protocol::ProcessMessage::Server(val) => {
+                protocol::ProcessMessage::Reconnected => {
+                    scheduler.push(Box::new(Reconnect::default())).await
+                }
+                protocol::ProcessMessage::Server(msg) => {
                     // Check for ping
-                    if val.get("msg").and_then(|m| m.as_str()) == Some("ping") {
-                        let tx_inner = tx_sender.clone();
+                    if let ServerMessage::Ping = msg {
+                        let tx_inner = tx_control.clone();
                         tokio::spawn(async move {
-                            let _ = tx_inner
-                                .send(Message::Text(r#"{"msg":"pong"}"#.into()))
-                                .await;
+                            let _ = tx_inner.send(ClientMessage::Pong).await;
                         });
                         continue;
                     }
 
-                    // Process with current routine
-                    // Manual peek:
-                    peeked = match rx_receiver.try_recv() {
-                        Ok(p) => Some(p),
-                        Err(_) => None,
-                    };
-                    let next_val = match &peeked {
-                        Some(protocol::ProcessMessage::Server(v)) => Some(v),
-                        _ => None,
-                    };
-
-                    let mut routine = current_routine.lock().await;
-                    let (new_state, outgoing) = commands::execute_routine(
-                        routine.clone(),
-                        Some(&val),
-                        next_val,
-                        &map_state,
-                        &logger,
-                    )
-                    .await;
-                    *routine = new_state;
-
-                    for msg_str in outgoing {
-                        let _ = tx_sender.send(Message::Text(msg_str.into())).await;
-                    }
+                    commands::update_state(&msg, &ctx).await;
+                    ctx.logger
+                        .log(&format!(
+                            "[ROUTIN]: {} handling '{}'\n",
+                            scheduler.describe(),
+                            msg.kind()
+                        ))
+                        .await;
+                    scheduler.handle(&msg).await
                 }
+            };
+
+            let popup_open = ctx.ui_stack.lock().await.top().is_some();
+            for client_msg in &outgoing {
+                ctx.account.lock().await.sent(client_msg);
+                ctx.input.lock().await.sent(client_msg, popup_open);
+            }
+            if !outgoing.is_empty() {
+                let _ = tx_sender.send(outgoing).await;
             }
         }
-    });
+
+        // Dropping `tx_sender` afterwards lets the sender flush and close.
+        scheduler.clear().await;
+    })
 }
 
 async fn run_repl(
     mut rl: Readline,
     logger: Logger,
     tx_receiver: mpsc::Sender<protocol::ProcessMessage>,
-    _current_routine: Arc<Mutex<Routine>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match rl.readline().await {
@@ -283,15 +509,9 @@ async fn run_repl(
                     continue;
                 }
 
-                if line.starts_with('/') {
-                    tx_receiver
-                        .send(protocol::ProcessMessage::Repl(line.to_string()))
-                        .await?;
-                } else {
-                    tx_receiver
-                        .send(protocol::ProcessMessage::Repl(line.to_string()))
-                        .await?;
-                }
+                tx_receiver
+                    .send(protocol::ProcessMessage::Repl(line.to_string()))
+                    .await?;
                 rl.add_history_entry(line.to_string());
             }
             Ok(ReadlineEvent::Eof) | Ok(ReadlineEvent::Interrupted) => break,
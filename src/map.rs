@@ -1,89 +1,342 @@
+pub mod glyph;
+pub mod position;
+
 #[allow(unused_imports)]
 use std::io::Write;
 
-const MAP_WIDTH: usize = 200;
-const MAP_HEIGHT: usize = 200;
+use crate::protocol::MapMessage;
+use glyph::{GlyphKind, MinimapFeature};
+pub use position::{GXM, GYM, Pos};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+/// A single cell of a `map` message. Every field is optional: the server only
+/// sends what changed since the last update.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Cell {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub y: Option<i32>,
+    /// Dungeon feature id (`dungeon_feature_type`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f: Option<i32>,
+    /// Minimap feature, see `minimap_colours` in minimap.js.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mf: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub g: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub col: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t: Option<Tile>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
 }
 
-pub struct MapState {
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Tile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<u64>,
+    /// `Some(None)` when the server sends `"mon": null` to clear the monster.
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mon: Option<Option<MonsterInfo>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MonsterInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plural: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub mon_type: Option<i32>,
+    /// `mon_attitude_type`: 0 hostile, 1-3 neutral, 4 friendly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub att: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threat: Option<i32>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn merge<T: Clone>(target: &mut Option<T>, update: &Option<T>) {
+    if update.is_some() {
+        target.clone_from(update);
+    }
+}
+
+impl MonsterInfo {
+    fn merge(&mut self, update: &MonsterInfo) {
+        merge(&mut self.id, &update.id);
+        merge(&mut self.name, &update.name);
+        merge(&mut self.plural, &update.plural);
+        merge(&mut self.mon_type, &update.mon_type);
+        merge(&mut self.att, &update.att);
+        merge(&mut self.threat, &update.threat);
+        for (key, value) in &update.other {
+            self.other.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Everything known about a map cell, accumulated over all updates.
+#[derive(Debug, Clone, Default)]
+pub struct MapCell {
+    pub glyph: Option<String>,
+    pub feature: Option<i32>,
+    pub minimap: Option<i32>,
+    pub colour: Option<i32>,
+    pub fg: Option<u64>,
+    pub bg: Option<u64>,
+    pub monster: Option<MonsterInfo>,
+}
+
+impl MapCell {
+    /// Merges a partial update: fields missing from `cell` keep their value.
+    pub fn update(&mut self, cell: &Cell) {
+        merge(&mut self.glyph, &cell.g);
+        merge(&mut self.feature, &cell.f);
+        merge(&mut self.minimap, &cell.mf);
+        merge(&mut self.colour, &cell.col);
+
+        // A minimap feature other than a monster means the monster is gone,
+        // even if no `"mon": null` came with it.
+        if cell
+            .mf
+            .is_some_and(|mf| !MinimapFeature::from_id(mf).is_monster())
+        {
+            self.monster = None;
+        }
+
+        if let Some(t) = &cell.t {
+            merge(&mut self.fg, &t.fg);
+            merge(&mut self.bg, &t.bg);
+
+            match &t.mon {
+                Some(None) => self.monster = None,
+                Some(Some(mon)) => match &mut self.monster {
+                    Some(current) if current.id == mon.id => current.merge(mon),
+                    _ => self.monster = Some(mon.clone()),
+                },
+                None => {}
+            }
+        }
+    }
+
+    /// Classification of the cell's glyph, `Unexplored` if none was seen yet.
+    pub fn kind(&self) -> GlyphKind {
+        self.glyph
+            .as_deref()
+            .map_or(GlyphKind::Unexplored, GlyphKind::from_glyph)
+    }
+
+    pub fn minimap_feature(&self) -> Option<MinimapFeature> {
+        self.minimap.map(MinimapFeature::from_id)
+    }
+
+    pub fn has_monster(&self) -> bool {
+        self.monster.is_some()
+            || self
+                .minimap_feature()
+                .is_some_and(MinimapFeature::is_monster)
+    }
+
+    pub fn is_passable(&self) -> bool {
+        self.kind().is_passable()
+    }
+}
+
+/// A dungeon level, identified by branch name and depth as reported in the
+/// `place` and `depth` fields of `player` messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LevelId {
+    pub place: String,
+    pub depth: i32,
+}
+
+impl std::fmt::Display for LevelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.place, self.depth)
+    }
+}
+
+/// Resolves the position of every cell of a `map` message. Cells without `x`
+/// continue the run of the previous cell (`x + 1`), cells without `y` stay on
+/// its row.
+pub fn decode_cells(cells: &[Cell]) -> impl Iterator<Item = (Pos, &Cell)> {
+    let mut last = Pos::new(-1, 0);
+    cells.iter().map(move |cell| {
+        last = Pos::new(cell.x.unwrap_or(last.x + 1), cell.y.unwrap_or(last.y));
+        (last, cell)
+    })
+}
+
+/// Everything known about the cells of a single level. The grid grows to
+/// cover whatever positions the server sends, up to `GXM` x `GYM`.
+#[derive(Clone, Default)]
+pub struct LevelMap {
+    min: Pos,
     width: usize,
     height: usize,
-    cells: Vec<Option<String>>,
+    cells: Vec<Option<MapCell>>,
 }
 
-impl MapState {
+impl LevelMap {
     pub fn new() -> Self {
-        Self {
-            width: MAP_WIDTH,
-            height: MAP_HEIGHT,
-            cells: vec![None; MAP_WIDTH * MAP_HEIGHT],
-        }
+        Self::default()
     }
 
-    pub async fn update_map(&mut self, cells: &[Cell], logger: &crate::logger::Logger) {
-        logger.log("updateMap\n").await;
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
 
-        let origin_x = (self.width / 2) as i32;
-        let origin_y = (self.height / 2) as i32;
-        let mut map_index: i32 = 0;
+    fn index(&self, pos: Pos) -> Option<usize> {
+        let x = usize::try_from(pos.x - self.min.x).ok()?;
+        let y = usize::try_from(pos.y - self.min.y).ok()?;
+        (x < self.width && y < self.height).then_some(x + y * self.width)
+    }
 
-        for cell in cells {
-            if let (Some(x), Some(y)) = (cell.x, cell.y) {
-                map_index = origin_x + x + (self.width as i32) * (origin_y + y);
-            } else {
-                map_index += 1;
-            }
+    /// Grows the grid to contain `pos`. Returns false if that would make the
+    /// level larger than a dungeon level can be.
+    fn ensure(&mut self, pos: Pos) -> bool {
+        if self.index(pos).is_some() {
+            return true;
+        }
 
-            if let Some(g) = &cell.g {
-                if map_index >= 0 && (map_index as usize) < self.cells.len() {
-                    self.cells[map_index as usize] = Some(g.clone());
-                }
+        let (min, max) = if self.cells.is_empty() {
+            (pos, pos)
+        } else {
+            let max = self
+                .min
+                .offset(self.width as i32 - 1, self.height as i32 - 1);
+            (
+                Pos::new(self.min.x.min(pos.x), self.min.y.min(pos.y)),
+                Pos::new(max.x.max(pos.x), max.y.max(pos.y)),
+            )
+        };
+        let width = (max.x - min.x + 1) as usize;
+        let height = (max.y - min.y + 1) as usize;
+        if width > GXM || height > GYM {
+            return false;
+        }
+
+        let mut cells = vec![None; width * height];
+        for (i, cell) in self.cells.drain(..).enumerate() {
+            let x = (self.min.x + (i % self.width) as i32 - min.x) as usize;
+            let y = (self.min.y + (i / self.width) as i32 - min.y) as usize;
+            cells[x + y * width] = cell;
+        }
+
+        self.min = min;
+        self.width = width;
+        self.height = height;
+        self.cells = cells;
+        true
+    }
+
+    pub fn update(&mut self, cells: &[Cell]) {
+        for (pos, cell) in decode_cells(cells) {
+            if self.ensure(pos)
+                && let Some(i) = self.index(pos)
+            {
+                self.cells[i]
+                    .get_or_insert_with(MapCell::default)
+                    .update(cell);
             }
         }
     }
 
+    pub fn get(&self, pos: Pos) -> Option<&MapCell> {
+        self.index(pos).and_then(|i| self.cells[i].as_ref())
+    }
+
+    /// All known cells with their positions.
+    pub fn cells(&self) -> impl Iterator<Item = (Pos, &MapCell)> {
+        self.cells.iter().enumerate().filter_map(|(i, cell)| {
+            let pos = self
+                .min
+                .offset((i % self.width) as i32, (i / self.width) as i32);
+            cell.as_ref().map(|c| (pos, c))
+        })
+    }
+
+    /// Cells currently showing a monster. Updates are diffs, so a monster
+    /// stays until the server clears it with `"mon": null` or a minimap
+    /// feature other than a monster, which it does once the monster moves or
+    /// leaves sight.
+    pub fn monsters(&self) -> impl Iterator<Item = (Pos, &MapCell)> {
+        self.cells().filter(|(_, cell)| cell.has_monster())
+    }
+
+    /// Positions of all known cells of the given kind, e.g. down stairs.
+    pub fn find(&self, kind: GlyphKind) -> Vec<Pos> {
+        self.cells()
+            .filter(|(_, cell)| cell.kind() == kind)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    /// Whether `pos` is a known, walkable cell next to one never seen.
+    pub fn is_frontier(&self, pos: Pos) -> bool {
+        let walkable = self
+            .get(pos)
+            .is_some_and(|cell| cell.is_passable() && !cell.kind().is_dangerous());
+        walkable
+            && pos
+                .neighbours()
+                .any(|n| self.get(n).is_none_or(|cell| cell.glyph.is_none()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(Option::is_none)
+    }
+
     pub fn print_map<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        let mut min_x = self.width;
-        let mut max_x = 0;
-        let mut min_y = self.height;
-        let mut max_y = 0;
-
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let i = x + y * self.width;
-                if self.cells[i].is_some() {
-                    if x < min_x {
-                        min_x = x;
-                    }
-                    if x > max_x {
-                        max_x = x;
-                    }
-                    if y < min_y {
-                        min_y = y;
-                    }
-                    if y > max_y {
-                        max_y = y;
-                    }
-                }
+        let mut bounds: Option<(Pos, Pos)> = None;
+        for (pos, cell) in self.cells() {
+            if cell.glyph.is_none() {
+                continue;
             }
+            bounds = Some(match bounds {
+                None => (pos, pos),
+                Some((min, max)) => (
+                    Pos::new(min.x.min(pos.x), min.y.min(pos.y)),
+                    Pos::new(max.x.max(pos.x), max.y.max(pos.y)),
+                ),
+            });
         }
 
-        if min_x > max_x || min_y > max_y {
+        let Some((min, max)) = bounds else {
             writeln!(writer, "Map is empty")?;
             return Ok(());
-        }
+        };
 
-        writeln!(writer, "{},{} - {},{}", min_x, min_y, max_x, max_y)?;
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                let i = x + y * self.width;
-                match &self.cells[i] {
+        writeln!(writer, "{},{} - {},{}", min.x, min.y, max.x, max.y)?;
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                match self.get(Pos::new(x, y)).and_then(|c| c.glyph.as_ref()) {
                     None => {
                         write!(writer, " ")?;
                     }
@@ -98,3 +351,173 @@ impl MapState {
         Ok(())
     }
 }
+
+/// Maps of all levels seen so far. Updates go to the level the player is
+/// currently on; previously explored levels are kept for later.
+pub struct MapState {
+    levels: HashMap<LevelId, LevelMap>,
+    current: LevelId,
+}
+
+impl MapState {
+    pub fn new() -> Self {
+        let current = LevelId::default();
+        let mut levels = HashMap::new();
+        levels.insert(current.clone(), LevelMap::new());
+        Self { levels, current }
+    }
+
+    /// Switches to `level`, creating an empty map for it on the first visit.
+    /// Returns whether the level changed.
+    pub fn set_level(&mut self, level: LevelId) -> bool {
+        if level == self.current {
+            return false;
+        }
+
+        // Cells received before the first `player` message belong to the
+        // level the player turns out to be on.
+        let unknown = LevelId::default();
+        if self.current == unknown {
+            let pending = self.levels.remove(&unknown).unwrap_or_default();
+            if !pending.is_empty() {
+                self.levels.entry(level.clone()).or_insert(pending);
+            }
+        }
+
+        self.levels.entry(level.clone()).or_default();
+        self.current = level;
+        true
+    }
+
+    pub fn current_id(&self) -> &LevelId {
+        &self.current
+    }
+
+    pub fn current(&self) -> &LevelMap {
+        &self.levels[&self.current]
+    }
+
+    fn current_mut(&mut self) -> &mut LevelMap {
+        self.levels
+            .get_mut(&self.current)
+            .expect("current level always has a map")
+    }
+
+    #[allow(dead_code)]
+    pub fn level(&self, level: &LevelId) -> Option<&LevelMap> {
+        self.levels.get(level)
+    }
+
+    #[allow(dead_code)]
+    pub fn known_levels(&self) -> impl Iterator<Item = &LevelId> {
+        self.levels.keys()
+    }
+
+    pub async fn update_map(&mut self, msg: &MapMessage, logger: &crate::logger::Logger) {
+        logger.log(&format!("updateMap {}\n", self.current)).await;
+
+        let level = self.current_mut();
+        if msg.clear == Some(true) {
+            level.clear();
+        }
+        level.update(&msg.cells);
+    }
+
+    pub fn print_map<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.current().print_map(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ServerMessage, parse_messages};
+
+    fn load_map(path: &str) -> MapMessage {
+        let raw = std::fs::read_to_string(path).unwrap();
+        parse_messages(serde_json::from_str(&raw).unwrap())
+            .into_iter()
+            .find_map(|msg| match msg {
+                ServerMessage::Map(map) => Some(map),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn glyph(level: &LevelMap, x: i32, y: i32) -> Option<&str> {
+        level.get(Pos::new(x, y))?.glyph.as_deref()
+    }
+
+    #[test]
+    fn implicit_x_continues_the_run() {
+        let msg = load_map("test/research/login/09-map.json");
+        let positions: Vec<Pos> = decode_cells(&msg.cells).map(|(pos, _)| pos).collect();
+
+        assert_eq!(positions[0], Pos::new(-3, -2));
+        assert_eq!(positions[1], Pos::new(-2, -2));
+        assert_eq!(positions[6], Pos::new(3, -2));
+        assert_eq!(positions[7], Pos::new(-3, -1));
+    }
+
+    #[test]
+    fn login_capture_places_features() {
+        let mut level = LevelMap::new();
+        level.update(&load_map("test/research/login/09-map.json").cells);
+
+        assert_eq!(glyph(&level, 0, 0), Some("@"));
+        assert_eq!(glyph(&level, 0, 2), Some(">"));
+        assert_eq!(glyph(&level, -1, 3), Some(")"));
+        assert_eq!(glyph(&level, -3, -2), Some("#"));
+        assert_eq!(glyph(&level, -5, 5), Some("#"));
+        assert_eq!(level.find(GlyphKind::Player), vec![Pos::new(0, 0)]);
+        // The player stands on the up stairs, which only the minimap shows.
+        assert_eq!(
+            level.get(Pos::new(0, 0)).unwrap().minimap_feature(),
+            Some(MinimapFeature::StairUp)
+        );
+        assert_eq!(level.find(GlyphKind::StairsDown), vec![Pos::new(0, 2)]);
+        assert_eq!(level.find(GlyphKind::Fountain).len(), 4);
+    }
+
+    #[test]
+    fn move_capture_merges_into_login_map() {
+        let mut state = MapState::new();
+        let login = load_map("test/research/login/09-map.json");
+        let step = load_map("test/research/move/01-msgs.json");
+        state.current_mut().update(&login.cells);
+        state.current_mut().update(&step.cells);
+
+        let level = state.current();
+        assert_eq!(glyph(level, 0, 0), Some("."));
+        assert_eq!(glyph(level, 1, 0), Some("@"));
+        // Cells updated without a glyph keep the one they had.
+        assert_eq!(glyph(level, -3, 4), Some("."));
+        assert_eq!(level.get(Pos::new(-3, 4)).unwrap().colour, Some(8));
+        assert_eq!(step.vgrdc.map(Pos::from), Some(Pos::new(1, 0)));
+    }
+
+    #[test]
+    fn grid_grows_in_every_direction() {
+        let mut level = LevelMap::new();
+        let cell = |x, y| Cell {
+            x: Some(x),
+            y: Some(y),
+            f: None,
+            mf: None,
+            g: Some(".".to_string()),
+            col: None,
+            t: None,
+            other: Map::new(),
+        };
+
+        level.update(&[cell(0, 0), cell(5, 3), cell(-4, -2)]);
+        assert_eq!(glyph(&level, 0, 0), Some("."));
+        assert_eq!(glyph(&level, 5, 3), Some("."));
+        assert_eq!(glyph(&level, -4, -2), Some("."));
+        assert_eq!(level.cells().count(), 3);
+
+        // A level can never be wider than GXM.
+        level.update(&[cell(-4 + GXM as i32, 0)]);
+        assert_eq!(level.cells().count(), 3);
+    }
+}
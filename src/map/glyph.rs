@@ -0,0 +1,198 @@
+//! Classification of map glyphs and minimap features, following
+//! `test/research/glyphs.md` and `map_feature` in crawl's map-feature.h.
+
+use crate::inventory::ItemClass;
+
+/// What a map glyph stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphKind {
+    Wall,
+    PermaWall,
+    MagicWall,
+    Floor,
+    MagicFloor,
+    DoorOpen,
+    DoorClosed,
+    Trap,
+    StairsDown,
+    StairsUp,
+    Altar,
+    Arch,
+    Fountain,
+    DeepLiquid,
+    ShallowWater,
+    Statue,
+    Tree,
+    Teleporter,
+    Cloud,
+    InvisibleMonster,
+    DetectedItem,
+    Item(ItemClass),
+    Player,
+    Monster,
+    Unexplored,
+    Unknown,
+}
+
+impl GlyphKind {
+    pub fn from_glyph(glyph: &str) -> Self {
+        let mut chars = glyph.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return GlyphKind::Unknown;
+        };
+
+        match c {
+            '#' => GlyphKind::Wall,
+            '▓' => GlyphKind::PermaWall,
+            '*' => GlyphKind::MagicWall,
+            '.' => GlyphKind::Floor,
+            ',' => GlyphKind::MagicFloor,
+            '\'' => GlyphKind::DoorOpen,
+            '+' => GlyphKind::DoorClosed,
+            '^' => GlyphKind::Trap,
+            '>' => GlyphKind::StairsDown,
+            '<' => GlyphKind::StairsUp,
+            '_' => GlyphKind::Altar,
+            '∩' => GlyphKind::Arch,
+            '⌠' => GlyphKind::Fountain,
+            '≈' => GlyphKind::DeepLiquid,
+            '~' => GlyphKind::ShallowWater,
+            'ß' => GlyphKind::Statue,
+            '♣' => GlyphKind::Tree,
+            '©' => GlyphKind::Teleporter,
+            '§' | '☼' | '○' | '°' => GlyphKind::Cloud,
+            '{' => GlyphKind::InvisibleMonster,
+            '∆' => GlyphKind::DetectedItem,
+            '0' => GlyphKind::Item(ItemClass::Orb),
+            'φ' => GlyphKind::Item(ItemClass::Rune),
+            ')' => GlyphKind::Item(ItemClass::Weapon),
+            '[' => GlyphKind::Item(ItemClass::Armour),
+            '/' => GlyphKind::Item(ItemClass::Wand),
+            '%' => GlyphKind::Item(ItemClass::Talisman),
+            '?' => GlyphKind::Item(ItemClass::Scroll),
+            '=' | '"' => GlyphKind::Item(ItemClass::Jewellery),
+            '!' => GlyphKind::Item(ItemClass::Potion),
+            '(' => GlyphKind::Item(ItemClass::Missile),
+            ':' => GlyphKind::Item(ItemClass::Book),
+            '|' => GlyphKind::Item(ItemClass::Staff),
+            '\\' => GlyphKind::Item(ItemClass::Rod),
+            '}' => GlyphKind::Item(ItemClass::Miscellany),
+            '†' | '÷' => GlyphKind::Item(ItemClass::Corpse),
+            '$' => GlyphKind::Item(ItemClass::Gold),
+            '♦' => GlyphKind::Item(ItemClass::Gem),
+            '•' => GlyphKind::Item(ItemClass::Bauble),
+            ' ' => GlyphKind::Unexplored,
+            '@' => GlyphKind::Player,
+            '&' => GlyphKind::Monster,
+            c if c.is_ascii_alphabetic() => GlyphKind::Monster,
+            _ => GlyphKind::Unknown,
+        }
+    }
+
+    /// Whether the player can walk onto the cell. Closed doors count as
+    /// passable since moving into them opens them.
+    pub fn is_passable(self) -> bool {
+        !matches!(
+            self,
+            GlyphKind::Wall
+                | GlyphKind::PermaWall
+                | GlyphKind::MagicWall
+                | GlyphKind::DeepLiquid
+                | GlyphKind::Statue
+                | GlyphKind::Tree
+                | GlyphKind::Unexplored
+                | GlyphKind::Unknown
+        )
+    }
+
+    /// Whether stepping onto the cell is harmful or risky.
+    pub fn is_dangerous(self) -> bool {
+        matches!(
+            self,
+            GlyphKind::Trap
+                | GlyphKind::DeepLiquid
+                | GlyphKind::Cloud
+                | GlyphKind::Monster
+                | GlyphKind::InvisibleMonster
+        )
+    }
+}
+
+/// Minimap feature (`mf`) of a map cell, `map_feature` in crawl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinimapFeature {
+    Unseen,
+    Floor,
+    Wall,
+    MapFloor,
+    MapWall,
+    Door,
+    Item,
+    MonsterFriendly,
+    MonsterPeaceful,
+    MonsterNeutral,
+    MonsterHostile,
+    MonsterNoExp,
+    StairUp,
+    StairDown,
+    StairBranch,
+    Feature,
+    Water,
+    Lava,
+    Trap,
+    ExclusionRoot,
+    Exclusion,
+    Player,
+    DeepWater,
+    Portal,
+    Transporter,
+    TransporterLanding,
+    ExploreHorizon,
+    Other(i32),
+}
+
+impl MinimapFeature {
+    pub fn from_id(id: i32) -> Self {
+        match id {
+            0 => MinimapFeature::Unseen,
+            1 => MinimapFeature::Floor,
+            2 => MinimapFeature::Wall,
+            3 => MinimapFeature::MapFloor,
+            4 => MinimapFeature::MapWall,
+            5 => MinimapFeature::Door,
+            6 => MinimapFeature::Item,
+            7 => MinimapFeature::MonsterFriendly,
+            8 => MinimapFeature::MonsterPeaceful,
+            9 => MinimapFeature::MonsterNeutral,
+            10 => MinimapFeature::MonsterHostile,
+            11 => MinimapFeature::MonsterNoExp,
+            12 => MinimapFeature::StairUp,
+            13 => MinimapFeature::StairDown,
+            14 => MinimapFeature::StairBranch,
+            15 => MinimapFeature::Feature,
+            16 => MinimapFeature::Water,
+            17 => MinimapFeature::Lava,
+            18 => MinimapFeature::Trap,
+            19 => MinimapFeature::ExclusionRoot,
+            20 => MinimapFeature::Exclusion,
+            21 => MinimapFeature::Player,
+            22 => MinimapFeature::DeepWater,
+            23 => MinimapFeature::Portal,
+            24 => MinimapFeature::Transporter,
+            25 => MinimapFeature::TransporterLanding,
+            26 => MinimapFeature::ExploreHorizon,
+            other => MinimapFeature::Other(other),
+        }
+    }
+
+    pub fn is_monster(self) -> bool {
+        matches!(
+            self,
+            MinimapFeature::MonsterFriendly
+                | MinimapFeature::MonsterPeaceful
+                | MinimapFeature::MonsterNeutral
+                | MinimapFeature::MonsterHostile
+                | MinimapFeature::MonsterNoExp
+        )
+    }
+}
@@ -0,0 +1,51 @@
+use crate::protocol::Coord;
+
+/// Width of a dungeon level in cells (`GXM` in crawl).
+pub const GXM: usize = 80;
+/// Height of a dungeon level in cells (`GYM` in crawl).
+pub const GYM: usize = 70;
+
+/// A cell on the current level, in the coordinate system the server uses for
+/// `map` cells and the player `pos`: relative to an origin it picks when the
+/// level is entered, so values may be negative but a level never spans more
+/// than `GXM` x `GYM` cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Pos {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy)
+    }
+
+    /// Number of king moves between two positions.
+    pub fn distance(self, other: Pos) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
+    /// The eight surrounding positions.
+    pub fn neighbours(self) -> impl Iterator<Item = Pos> {
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+            .map(move |(dx, dy)| self.offset(dx, dy))
+    }
+}
+
+impl From<Coord> for Pos {
+    fn from(coord: Coord) -> Self {
+        Self::new(coord.x, coord.y)
+    }
+}
+
+impl std::fmt::Display for Pos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
@@ -0,0 +1,360 @@
+use crate::protocol::MsgsMessage;
+use regex::Regex;
+use std::collections::VecDeque;
+
+const LOG_CAPACITY: usize = 500;
+
+/// Text colours used in the inline `<colour>` markup of game messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    Black,
+    Blue,
+    Green,
+    Cyan,
+    Red,
+    Magenta,
+    Brown,
+    LightGrey,
+    DarkGrey,
+    LightBlue,
+    LightGreen,
+    LightCyan,
+    LightRed,
+    LightMagenta,
+    Yellow,
+    White,
+}
+
+impl Colour {
+    fn from_tag(tag: &str) -> Option<Self> {
+        let colour = match tag {
+            "black" => Colour::Black,
+            "blue" => Colour::Blue,
+            "green" => Colour::Green,
+            "cyan" => Colour::Cyan,
+            "red" => Colour::Red,
+            "magenta" => Colour::Magenta,
+            "brown" => Colour::Brown,
+            "lightgrey" | "lightgray" => Colour::LightGrey,
+            "darkgrey" | "darkgray" => Colour::DarkGrey,
+            "lightblue" => Colour::LightBlue,
+            "lightgreen" => Colour::LightGreen,
+            "lightcyan" => Colour::LightCyan,
+            "lightred" => Colour::LightRed,
+            "lightmagenta" => Colour::LightMagenta,
+            "yellow" => Colour::Yellow,
+            "white" => Colour::White,
+            _ => return None,
+        };
+        Some(colour)
+    }
+}
+
+/// `msg_channel_type` from crawl's message.h.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Plain,
+    FriendAction,
+    Prompt,
+    God,
+    Duration,
+    Danger,
+    Warn,
+    Recovery,
+    Sound,
+    Talk,
+    TalkVisual,
+    IntrinsicGain,
+    Mutation,
+    MonsterSpell,
+    MonsterEnchant,
+    FriendSpell,
+    FriendEnchant,
+    MonsterDamage,
+    MonsterTarget,
+    Banishment,
+    Equipment,
+    FloorItems,
+    MultiturnAction,
+    Examine,
+    ExamineFilter,
+    Diagnostics,
+    Error,
+    Tutorial,
+    Orb,
+    TimedPortal,
+    HellEffect,
+    MonsterWarning,
+    DglMessage,
+    Other(i32),
+}
+
+impl Channel {
+    pub fn from_id(id: i32) -> Self {
+        match id {
+            0 => Channel::Plain,
+            1 => Channel::FriendAction,
+            2 => Channel::Prompt,
+            3 => Channel::God,
+            4 => Channel::Duration,
+            5 => Channel::Danger,
+            6 => Channel::Warn,
+            7 => Channel::Recovery,
+            8 => Channel::Sound,
+            9 => Channel::Talk,
+            10 => Channel::TalkVisual,
+            11 => Channel::IntrinsicGain,
+            12 => Channel::Mutation,
+            13 => Channel::MonsterSpell,
+            14 => Channel::MonsterEnchant,
+            15 => Channel::FriendSpell,
+            16 => Channel::FriendEnchant,
+            17 => Channel::MonsterDamage,
+            18 => Channel::MonsterTarget,
+            19 => Channel::Banishment,
+            20 => Channel::Equipment,
+            21 => Channel::FloorItems,
+            22 => Channel::MultiturnAction,
+            23 => Channel::Examine,
+            24 => Channel::ExamineFilter,
+            25 => Channel::Diagnostics,
+            26 => Channel::Error,
+            27 => Channel::Tutorial,
+            28 => Channel::Orb,
+            29 => Channel::TimedPortal,
+            30 => Channel::HellEffect,
+            31 => Channel::MonsterWarning,
+            32 => Channel::DglMessage,
+            other => Channel::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub colour: Option<Colour>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub turn: i64,
+    pub channel: Channel,
+    pub spans: Vec<Span>,
+}
+
+impl LogEntry {
+    pub fn new(markup: &str, turn: i64, channel: Channel) -> Self {
+        Self {
+            turn,
+            channel,
+            spans: parse_markup(markup),
+        }
+    }
+
+    /// The message with all colour markup removed.
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|s| s.text.as_str()).collect()
+    }
+}
+
+/// Splits `<colour>text</colour>` markup into styled spans. Closing tags go
+/// back to the colour before the matching opening tag. `<<` is a literal `<`
+/// and unknown tags are kept as text.
+pub fn parse_markup(markup: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut colours: Vec<Colour> = Vec::new();
+    let mut text = String::new();
+    let mut rest = markup;
+
+    while let Some(open) = rest.find('<') {
+        text.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        if let Some(after) = rest.strip_prefix("<<") {
+            text.push('<');
+            rest = after;
+            continue;
+        }
+
+        let tag = rest[1..]
+            .find('>')
+            .map(|close| &rest[1..close + 1])
+            .and_then(|tag| {
+                let (closing, name) = match tag.strip_prefix('/') {
+                    Some(name) => (true, name),
+                    None => (false, tag),
+                };
+                Colour::from_tag(name).map(|c| (tag, closing, c))
+            });
+
+        match tag {
+            Some((tag, closing, tag_colour)) => {
+                if !text.is_empty() {
+                    spans.push(Span {
+                        text: std::mem::take(&mut text),
+                        colour: colours.last().copied(),
+                    });
+                }
+                if closing {
+                    colours.pop();
+                } else {
+                    colours.push(tag_colour);
+                }
+                rest = &rest[tag.len() + 2..];
+            }
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        spans.push(Span {
+            text,
+            colour: colours.last().copied(),
+        });
+    }
+    spans
+}
+
+/// The plain text of a message, with all colour markup removed.
+pub fn strip_markup(markup: &str) -> String {
+    parse_markup(markup).into_iter().map(|s| s.text).collect()
+}
+
+struct Subscription {
+    id: SubscriptionId,
+    pattern: Regex,
+    matches: Vec<LogEntry>,
+}
+
+/// Ring buffer of the most recent game messages, fed from `msgs` messages.
+///
+/// Routines can either search the buffer directly or subscribe a pattern and
+/// collect the entries that matched it since they last looked.
+pub struct MessageLog {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    subscriptions: Vec<Subscription>,
+    next_subscription: SubscriptionId,
+}
+
+pub type SubscriptionId = usize;
+
+impl MessageLog {
+    pub fn new() -> Self {
+        Self::with_capacity(LOG_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            subscriptions: Vec::new(),
+            next_subscription: 0,
+        }
+    }
+
+    /// Appends the messages of a `msgs` message and returns the new entries.
+    pub fn update(&mut self, msg: &MsgsMessage) -> Vec<LogEntry> {
+        let mut added = Vec::with_capacity(msg.messages.len());
+        for m in &msg.messages {
+            let entry = LogEntry::new(
+                &m.text,
+                m.turn.unwrap_or_default(),
+                Channel::from_id(m.channel.unwrap_or_default()),
+            );
+
+            for sub in &mut self.subscriptions {
+                if sub.pattern.is_match(&entry.text()) {
+                    sub.matches.push(entry.clone());
+                }
+            }
+
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry.clone());
+            added.push(entry);
+        }
+        added
+    }
+
+    /// The buffered entries, oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn subscribe(&mut self, pattern: Regex) -> SubscriptionId {
+        let id = self.next_subscription;
+        self.next_subscription += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            pattern,
+            matches: Vec::new(),
+        });
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscriptions.retain(|sub| sub.id != id);
+    }
+
+    /// Entries that matched the subscription since the last call.
+    pub fn take_matches(&mut self, id: SubscriptionId) -> Vec<LogEntry> {
+        self.subscriptions
+            .iter_mut()
+            .find(|sub| sub.id == id)
+            .map(|sub| std::mem::take(&mut sub.matches))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, colour: Option<Colour>) -> Span {
+        Span {
+            text: text.to_string(),
+            colour,
+        }
+    }
+
+    #[test]
+    fn closing_tags_restore_the_outer_colour() {
+        let spans =
+            parse_markup("<yellow>You see <lightred>a goblin</lightred> here.</yellow> Done");
+        assert_eq!(
+            spans,
+            vec![
+                span("You see ", Some(Colour::Yellow)),
+                span("a goblin", Some(Colour::LightRed)),
+                span(" here.", Some(Colour::Yellow)),
+                span(" Done", None),
+            ]
+        );
+        assert_eq!(
+            strip_markup("<lightred>You die...</lightred>"),
+            "You die..."
+        );
+    }
+
+    #[test]
+    fn log_entries_keep_their_spans() {
+        let entry = LogEntry::new("<lightred>You die...</lightred>", 12, Channel::from_id(0));
+        assert_eq!(
+            entry.spans,
+            vec![span("You die...", Some(Colour::LightRed))]
+        );
+        assert_eq!(entry.text(), "You die...");
+    }
+
+    #[test]
+    fn literal_and_unknown_tags_stay_text() {
+        assert_eq!(strip_markup("a <<b> </c> <d"), "a <b> </c> <d");
+    }
+}
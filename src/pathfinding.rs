@@ -0,0 +1,275 @@
+use crate::map::glyph::GlyphKind;
+use crate::map::{LevelMap, MapCell, Pos};
+use crate::protocol::ClientMessage;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+const FLOOR_COST: u32 = 10;
+const DOOR_COST: u32 = 20;
+const SHALLOW_WATER_COST: u32 = 20;
+const CLOUD_COST: u32 = 80;
+
+/// Cost of stepping onto `cell`, `None` if the planner should never enter it.
+///
+/// Closed doors are passable at a small extra cost since moving into them opens
+/// them. Traps, deep liquids and monsters are avoided entirely.
+pub fn step_cost(cell: &MapCell) -> Option<u32> {
+    if cell.has_monster() {
+        return None;
+    }
+
+    match cell.kind() {
+        GlyphKind::DoorClosed => Some(DOOR_COST),
+        GlyphKind::ShallowWater => Some(SHALLOW_WATER_COST),
+        GlyphKind::Cloud => Some(CLOUD_COST),
+        GlyphKind::Trap | GlyphKind::Monster | GlyphKind::InvisibleMonster => None,
+        kind if kind.is_passable() => Some(FLOOR_COST),
+        _ => None,
+    }
+}
+
+/// The `hjklyubn` key that moves one step from `from` to the adjacent `to`.
+pub fn direction_key(from: Pos, to: Pos) -> Option<char> {
+    let key = match (to.x - from.x, to.y - from.y) {
+        (-1, 0) => 'h',
+        (0, 1) => 'j',
+        (0, -1) => 'k',
+        (1, 0) => 'l',
+        (-1, -1) => 'y',
+        (1, -1) => 'u',
+        (-1, 1) => 'b',
+        (1, 1) => 'n',
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Keystrokes walking along `path`, which starts at the first step after
+/// `from`. Moving into a closed door only opens it, so door steps get their key
+/// twice.
+pub fn path_to_keys(level: &LevelMap, from: Pos, path: &[Pos]) -> String {
+    let mut keys = String::with_capacity(path.len());
+    let mut current = from;
+    for &next in path {
+        let Some(key) = direction_key(current, next) else {
+            break;
+        };
+        keys.push(key);
+        if level
+            .get(next)
+            .is_some_and(|cell| cell.kind() == GlyphKind::DoorClosed)
+        {
+            keys.push(key);
+        }
+        current = next;
+    }
+    keys
+}
+
+/// One input message per key of [`path_to_keys`].
+pub fn path_to_messages(level: &LevelMap, from: Pos, path: &[Pos]) -> Vec<ClientMessage> {
+    path_to_keys(level, from, path)
+        .chars()
+        .map(|key| ClientMessage::input(&key.to_string()))
+        .collect()
+}
+
+fn walk_back(came_from: &HashMap<Pos, Pos>, from: Pos, to: Pos) -> Vec<Pos> {
+    let mut path = vec![to];
+    let mut current = to;
+    while let Some(&prev) = came_from.get(&current) {
+        if prev == from {
+            break;
+        }
+        path.push(prev);
+        current = prev;
+    }
+    path.reverse();
+    path
+}
+
+/// Cost of entering `pos`, where the target itself may be occupied (e.g. a
+/// monster to attack or a trap to deliberately step on).
+fn cost_at(level: &LevelMap, pos: Pos, is_target: bool) -> Option<u32> {
+    let cell = level.get(pos)?;
+    match step_cost(cell) {
+        Some(cost) => Some(cost),
+        None if is_target && cell.glyph.is_some() => Some(FLOOR_COST),
+        None => None,
+    }
+}
+
+/// Shortest 8-directional path from `from` to `to` using A*. The returned path
+/// excludes `from` and ends at `to`; it is empty if both are the same.
+pub fn find_path(level: &LevelMap, from: Pos, to: Pos) -> Option<Vec<Pos>> {
+    if from == to {
+        return Some(Vec::new());
+    }
+
+    let heuristic = |pos: Pos| pos.distance(to) as u32 * FLOOR_COST;
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Pos, u32> = HashMap::new();
+    let mut came_from: HashMap<Pos, Pos> = HashMap::new();
+
+    best.insert(from, 0);
+    open.push(Reverse((heuristic(from), from)));
+
+    while let Some(Reverse((_, current))) = open.pop() {
+        if current == to {
+            return Some(walk_back(&came_from, from, to));
+        }
+
+        let cost = best[&current];
+        for next in current.neighbours() {
+            let Some(step) = cost_at(level, next, next == to) else {
+                continue;
+            };
+            let next_cost = cost + step;
+            if best.get(&next).is_none_or(|&known| next_cost < known) {
+                best.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + heuristic(next), next)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Dijkstra search from `from` to the cheapest cell satisfying `goal`. Goal
+/// cells may be ones the planner would otherwise avoid.
+pub fn find_nearest<F>(level: &LevelMap, from: Pos, goal: F) -> Option<Vec<Pos>>
+where
+    F: Fn(Pos, &MapCell) -> bool,
+{
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Pos, u32> = HashMap::new();
+    let mut came_from: HashMap<Pos, Pos> = HashMap::new();
+
+    best.insert(from, 0);
+    open.push(Reverse((0, from)));
+
+    while let Some(Reverse((cost, current))) = open.pop() {
+        if cost > best[&current] {
+            continue;
+        }
+        if current != from && level.get(current).is_some_and(|cell| goal(current, cell)) {
+            return Some(walk_back(&came_from, from, current));
+        }
+
+        for next in current.neighbours() {
+            let is_goal = level.get(next).is_some_and(|cell| goal(next, cell));
+            let Some(step) = cost_at(level, next, is_goal) else {
+                continue;
+            };
+            let next_cost = cost + step;
+            if best.get(&next).is_none_or(|&known| next_cost < known) {
+                best.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost, next)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Keystrokes to travel from `from` to `to`, if a route is known.
+#[allow(dead_code)]
+pub fn travel(level: &LevelMap, from: Pos, to: Pos) -> Option<Vec<ClientMessage>> {
+    find_path(level, from, to).map(|path| path_to_messages(level, from, &path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Cell;
+    use serde_json::Map;
+
+    /// A level drawn as rows of glyphs, with the top left corner at (0, 0).
+    fn level(rows: &[&str]) -> LevelMap {
+        let cells: Vec<Cell> = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars().enumerate().map(move |(x, g)| Cell {
+                    x: Some(x as i32),
+                    y: Some(y as i32),
+                    f: None,
+                    mf: None,
+                    g: Some(g.to_string()),
+                    col: None,
+                    t: None,
+                    other: Map::new(),
+                })
+            })
+            .collect();
+        let mut level = LevelMap::new();
+        level.update(&cells);
+        level
+    }
+
+    #[test]
+    fn path_goes_around_walls_diagonally() {
+        let level = level(&[
+            "#######", //
+            "#@.#..#", //
+            "#..#..#", //
+            "#.....#", //
+            "#######",
+        ]);
+        let from = Pos::new(1, 1);
+        let path = find_path(&level, from, Pos::new(5, 1)).unwrap();
+
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.last(), Some(&Pos::new(5, 1)));
+        assert!(
+            path.iter()
+                .all(|&pos| level.get(pos).unwrap().kind() != GlyphKind::Wall)
+        );
+        assert_eq!(path_to_keys(&level, from, &path), "nnuu");
+        assert_eq!(find_path(&level, from, from), Some(Vec::new()));
+    }
+
+    #[test]
+    fn walls_without_a_gap_block_the_path() {
+        let level = level(&[
+            "#####", //
+            "#@#.#", //
+            "#####",
+        ]);
+        assert_eq!(find_path(&level, Pos::new(1, 1), Pos::new(3, 1)), None);
+    }
+
+    #[test]
+    fn closed_doors_take_two_keys() {
+        let level = level(&[
+            "#####", //
+            "#@+.#", //
+            "#####",
+        ]);
+        let from = Pos::new(1, 1);
+        let path = find_path(&level, from, Pos::new(3, 1)).unwrap();
+
+        assert_eq!(path, vec![Pos::new(2, 1), Pos::new(3, 1)]);
+        assert_eq!(path_to_keys(&level, from, &path), "lll");
+        assert_eq!(path_to_messages(&level, from, &path).len(), 3);
+    }
+
+    #[test]
+    fn nearest_goal_may_be_a_cell_otherwise_avoided() {
+        let level = level(&[
+            "#######", //
+            "#@..^.#", //
+            "#.....#", //
+            "#######",
+        ]);
+        let from = Pos::new(1, 1);
+        let path = find_nearest(&level, from, |_, cell| cell.kind() == GlyphKind::Trap).unwrap();
+        assert_eq!(path.last(), Some(&Pos::new(4, 1)));
+        assert_eq!(path.len(), 3);
+
+        let stairs = find_nearest(&level, from, |_, cell| cell.kind() == GlyphKind::StairsDown);
+        assert_eq!(stairs, None);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::inventory::Inventory;
+use crate::map::Pos;
+use crate::protocol::{PlayerMessage, StatusEntry};
+
+/// Consistent snapshot of the player, built by merging the partial `player`
+/// messages the server sends whenever something changes.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerState {
+    pub name: String,
+    pub title: String,
+    pub species: String,
+    pub god: String,
+    pub piety_rank: i32,
+    pub hp: i32,
+    pub hp_max: i32,
+    pub mp: i32,
+    pub mp_max: i32,
+    pub ac: i32,
+    pub ev: i32,
+    pub sh: i32,
+    pub strength: i32,
+    pub intelligence: i32,
+    pub dexterity: i32,
+    pub xl: i32,
+    pub gold: i32,
+    pub time: i64,
+    pub turn: i64,
+    pub place: String,
+    pub depth: i32,
+    pub pos: Pos,
+    pub status: Vec<StatusEntry>,
+    pub inventory: Inventory,
+}
+
+fn merge<T: Clone>(target: &mut T, update: &Option<T>) {
+    if let Some(value) = update {
+        *target = value.clone();
+    }
+}
+
+impl PlayerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, msg: &PlayerMessage) {
+        merge(&mut self.name, &msg.name);
+        merge(&mut self.title, &msg.title);
+        merge(&mut self.species, &msg.species);
+        merge(&mut self.god, &msg.god);
+        merge(&mut self.piety_rank, &msg.piety_rank);
+        merge(&mut self.hp, &msg.hp);
+        merge(&mut self.hp_max, &msg.hp_max);
+        merge(&mut self.mp, &msg.mp);
+        merge(&mut self.mp_max, &msg.mp_max);
+        merge(&mut self.ac, &msg.ac);
+        merge(&mut self.ev, &msg.ev);
+        merge(&mut self.sh, &msg.sh);
+        merge(&mut self.strength, &msg.strength);
+        merge(&mut self.intelligence, &msg.intelligence);
+        merge(&mut self.dexterity, &msg.dexterity);
+        merge(&mut self.xl, &msg.xl);
+        merge(&mut self.gold, &msg.gold);
+        merge(&mut self.time, &msg.time);
+        merge(&mut self.turn, &msg.turn);
+        merge(&mut self.place, &msg.place);
+        merge(&mut self.depth, &msg.depth);
+        if let Some(pos) = msg.pos {
+            self.pos = pos.into();
+        }
+        merge(&mut self.status, &msg.status);
+
+        if let Some(inv) = &msg.inv {
+            self.inventory.update(inv);
+        }
+    }
+
+    /// Current HP as a fraction of max HP, 1.0 before the first update.
+    pub fn hp_fraction(&self) -> f32 {
+        if self.hp_max <= 0 {
+            return 1.0;
+        }
+        self.hp as f32 / self.hp_max as f32
+    }
+
+    /// Current MP as a fraction of max MP, 1.0 for characters without MP.
+    pub fn mp_fraction(&self) -> f32 {
+        if self.mp_max <= 0 {
+            return 1.0;
+        }
+        self.mp as f32 / self.mp_max as f32
+    }
+
+    #[allow(dead_code)]
+    pub fn has_status(&self, light: &str) -> bool {
+        self.status
+            .iter()
+            .any(|s| s.light.as_deref() == Some(light))
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} XL{} HP {}/{} MP {}/{} AC{} EV{} SH{} {}:{} ({},{}) T{} items {} ({} unidentified)",
+            self.name,
+            self.xl,
+            self.hp,
+            self.hp_max,
+            self.mp,
+            self.mp_max,
+            self.ac,
+            self.ev,
+            self.sh,
+            self.place,
+            self.depth,
+            self.pos.x,
+            self.pos.y,
+            self.turn,
+            self.inventory.len(),
+            self.inventory
+                .items()
+                .filter(|item| !item.is_identified())
+                .count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ServerMessage, parse_messages};
+
+    /// Applies the `player` messages of a capture. Transcripts of a session
+    /// carry the server's reply on their `[Server]: ` line.
+    fn apply(player: &mut PlayerState, path: &str) {
+        let raw = std::fs::read_to_string(path).unwrap();
+        let json = raw
+            .lines()
+            .find_map(|line| line.strip_prefix("[Server]: "))
+            .unwrap_or(&raw);
+        for msg in parse_messages(serde_json::from_str(json).unwrap()) {
+            if let ServerMessage::Player(msg) = msg {
+                player.update(&msg);
+            }
+        }
+    }
+
+    #[test]
+    fn partial_updates_keep_the_other_fields() {
+        let mut player = PlayerState::new();
+        apply(&mut player, "test/research/login/07-player.json");
+        assert_eq!(player.pos, Pos { x: 0, y: 0 });
+        assert_eq!(player.turn, 12);
+
+        apply(&mut player, "test/research/move/00-move.json");
+        assert_eq!(player.pos, Pos { x: 1, y: 0 });
+        assert_eq!(player.time, 200);
+        assert_eq!(player.turn, 20);
+        assert_eq!((player.hp, player.hp_max), (22, 22));
+        assert_eq!((player.place.as_str(), player.depth), ("Dungeon", 1));
+        assert_eq!(player.name, "dirkle");
+        assert_eq!(player.species, "Troll");
+        assert_eq!(player.god, "Trog");
+        assert_eq!(
+            (player.strength, player.intelligence, player.dexterity),
+            (24, 3, 9)
+        );
+        assert_eq!(player.inventory.len(), 1);
+    }
+}
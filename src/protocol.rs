@@ -1,20 +1,500 @@
 use crate::map::Cell;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct GameMessage {
-    pub msg: String,
+/// A single message sent by the webtiles server, tagged by its `msg` field.
+///
+/// Every payload keeps the fields it does not model in `other`, so a message
+/// can be serialized back into the exact JSON it was parsed from. Messages with
+/// an unrecognized `msg` (or a payload that does not fit its variant) end up in
+/// `Unknown` with the raw JSON untouched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "msg")]
+pub enum ServerMessage {
+    #[serde(rename = "ping")]
+    Ping,
+    #[serde(rename = "html")]
+    Html(HtmlMessage),
+    #[serde(rename = "set_game_links")]
+    SetGameLinks(HtmlMessage),
+    #[serde(rename = "lobby_clear")]
+    LobbyClear,
+    #[serde(rename = "lobby_complete")]
+    LobbyComplete,
+    #[serde(rename = "lobby_entry")]
+    LobbyEntry(Fields),
+    #[serde(rename = "lobby_remove")]
+    LobbyRemove(Fields),
+    #[serde(rename = "go_lobby")]
+    GoLobby,
+    #[serde(rename = "login_success")]
+    LoginSuccess(LoginSuccessMessage),
+    #[serde(rename = "login_fail")]
+    LoginFail(Fields),
+    #[serde(rename = "login_cookie")]
+    LoginCookie(Fields),
+    #[serde(rename = "register_fail")]
+    RegisterFail(FailMessage),
+    #[serde(rename = "game_client")]
+    GameClient(GameClientMessage),
+    #[serde(rename = "game_started")]
+    GameStarted,
+    #[serde(rename = "game_ended")]
+    GameEnded(Fields),
+    #[serde(rename = "update_spectators")]
+    UpdateSpectators(UpdateSpectatorsMessage),
+    #[serde(rename = "chat")]
+    Chat(ChatMessage),
+    #[serde(rename = "version")]
+    Version(VersionMessage),
+    #[serde(rename = "options")]
+    Options(OptionsMessage),
+    #[serde(rename = "layout")]
+    Layout(Fields),
+    #[serde(rename = "ui_state")]
+    UiState(UiStateMessage),
+    #[serde(rename = "ui-state")]
+    UiPopupState(Fields),
+    #[serde(rename = "ui-state-sync")]
+    UiStateSync(Fields),
+    #[serde(rename = "ui-push")]
+    UiPush(UiPushMessage),
+    #[serde(rename = "ui-pop")]
+    UiPop,
+    #[serde(rename = "ui-scroll")]
+    UiScroll(Fields),
+    #[serde(rename = "player")]
+    Player(Box<PlayerMessage>),
+    #[serde(rename = "map")]
+    Map(MapMessage),
+    #[serde(rename = "msgs")]
+    Msgs(MsgsMessage),
+    #[serde(rename = "input_mode")]
+    InputMode(InputModeMessage),
+    #[serde(rename = "cursor")]
+    Cursor(CursorMessage),
+    #[serde(rename = "text_cursor")]
+    TextCursor(Fields),
+    #[serde(rename = "menu")]
+    Menu(Fields),
+    #[serde(rename = "update_menu")]
+    UpdateMenu(Fields),
+    #[serde(rename = "update_menu_items")]
+    UpdateMenuItems(Fields),
+    #[serde(rename = "close_menu")]
+    CloseMenu,
+    #[serde(rename = "close_all_menus")]
+    CloseAllMenus,
+    #[serde(rename = "txt")]
+    Txt(Fields),
+    #[serde(rename = "delay")]
+    Delay(Fields),
+    #[serde(rename = "flash")]
+    Flash(Fields),
+    #[serde(rename = "dump")]
+    Dump(Fields),
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// Untyped remainder of a message payload.
+pub type Fields = Map<String, Value>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Coord {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HtmlMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoginSuccessMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FailMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GameClientMessage {
+    pub version: String,
+    pub content: String,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpdateSpectatorsMessage {
+    pub count: i64,
+    pub names: String,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChatMessage {
+    pub content: String,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VersionMessage {
+    pub text: String,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OptionsMessage {
+    pub options: Fields,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UiStateMessage {
+    pub state: i32,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UiPushMessage {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ui_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+/// Partial player state; the server only sends the fields that changed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlayerMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
-    pub cells: Option<Vec<Cell>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub species: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub god: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub piety_rank: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hp: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hp_max: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mp: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mp_max: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ac: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ev: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sh: Option<i32>,
+    #[serde(rename = "str", skip_serializing_if = "Option::is_none")]
+    pub strength: Option<i32>,
+    #[serde(rename = "int", skip_serializing_if = "Option::is_none")]
+    pub intelligence: Option<i32>,
+    #[serde(rename = "dex", skip_serializing_if = "Option::is_none")]
+    pub dexterity: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xl: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gold: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub place: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<Coord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Vec<StatusEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inv: Option<BTreeMap<String, ItemMessage>>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+/// Partial update of a single inventory slot.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ItemMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_type: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_type: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plus: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plus2: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inscription: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub useless: Option<i32>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatusEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub col: Option<i32>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MapMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_on_level: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vgrdc: Option<Coord>,
+    #[serde(default)]
+    pub cells: Vec<Cell>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MsgsMessage {
+    #[serde(default)]
+    pub messages: Vec<MessageEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub more: Option<bool>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageEntry {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<i32>,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InputModeMessage {
+    pub mode: i32,
+    #[serde(flatten)]
+    pub other: Fields,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CursorMessage {
+    pub id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loc: Option<Coord>,
     #[serde(flatten)]
-    pub other: serde_json::Map<String, Value>,
+    pub other: Fields,
+}
+
+impl ServerMessage {
+    /// The `msg` tag of this message as sent by the server.
+    pub fn kind(&self) -> &str {
+        match self {
+            ServerMessage::Ping => "ping",
+            ServerMessage::Html(_) => "html",
+            ServerMessage::SetGameLinks(_) => "set_game_links",
+            ServerMessage::LobbyClear => "lobby_clear",
+            ServerMessage::LobbyComplete => "lobby_complete",
+            ServerMessage::LobbyEntry(_) => "lobby_entry",
+            ServerMessage::LobbyRemove(_) => "lobby_remove",
+            ServerMessage::GoLobby => "go_lobby",
+            ServerMessage::LoginSuccess(_) => "login_success",
+            ServerMessage::LoginFail(_) => "login_fail",
+            ServerMessage::LoginCookie(_) => "login_cookie",
+            ServerMessage::RegisterFail(_) => "register_fail",
+            ServerMessage::GameClient(_) => "game_client",
+            ServerMessage::GameStarted => "game_started",
+            ServerMessage::GameEnded(_) => "game_ended",
+            ServerMessage::UpdateSpectators(_) => "update_spectators",
+            ServerMessage::Chat(_) => "chat",
+            ServerMessage::Version(_) => "version",
+            ServerMessage::Options(_) => "options",
+            ServerMessage::Layout(_) => "layout",
+            ServerMessage::UiState(_) => "ui_state",
+            ServerMessage::UiPopupState(_) => "ui-state",
+            ServerMessage::UiStateSync(_) => "ui-state-sync",
+            ServerMessage::UiPush(_) => "ui-push",
+            ServerMessage::UiPop => "ui-pop",
+            ServerMessage::UiScroll(_) => "ui-scroll",
+            ServerMessage::Player(_) => "player",
+            ServerMessage::Map(_) => "map",
+            ServerMessage::Msgs(_) => "msgs",
+            ServerMessage::InputMode(_) => "input_mode",
+            ServerMessage::Cursor(_) => "cursor",
+            ServerMessage::TextCursor(_) => "text_cursor",
+            ServerMessage::Menu(_) => "menu",
+            ServerMessage::UpdateMenu(_) => "update_menu",
+            ServerMessage::UpdateMenuItems(_) => "update_menu_items",
+            ServerMessage::CloseMenu => "close_menu",
+            ServerMessage::CloseAllMenus => "close_all_menus",
+            ServerMessage::Txt(_) => "txt",
+            ServerMessage::Delay(_) => "delay",
+            ServerMessage::Flash(_) => "flash",
+            ServerMessage::Dump(_) => "dump",
+            ServerMessage::Unknown(value) => value
+                .get("msg")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown"),
+        }
+    }
+}
+
+/// A single message sent to the webtiles server, tagged by its `msg` field.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "msg", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Register {
+        username: String,
+        password: String,
+        email: String,
+    },
+    Login {
+        username: String,
+        password: String,
+    },
+    Play {
+        game_id: String,
+    },
+    Input {
+        text: String,
+    },
+    Key {
+        keycode: i32,
+    },
+    Pong,
+    GoLobby,
+    ChatMsg {
+        text: String,
+    },
+    #[serde(rename = "watch")]
+    Spectate {
+        username: String,
+    },
+}
+
+impl ClientMessage {
+    pub fn input(text: &str) -> Self {
+        ClientMessage::Input {
+            text: text.to_string(),
+        }
+    }
+
+    pub fn key(keycode: i32) -> Self {
+        ClientMessage::Key { keycode }
+    }
+
+    pub fn play(game_id: &str) -> Self {
+        ClientMessage::Play {
+            game_id: game_id.to_string(),
+        }
+    }
+
+    /// The `msg` tag this message is sent with.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::Register { .. } => "register",
+            ClientMessage::Login { .. } => "login",
+            ClientMessage::Play { .. } => "play",
+            ClientMessage::Input { .. } => "input",
+            ClientMessage::Key { .. } => "key",
+            ClientMessage::Pong => "pong",
+            ClientMessage::GoLobby => "go_lobby",
+            ClientMessage::ChatMsg { .. } => "chat_msg",
+            ClientMessage::Spectate { .. } => "watch",
+        }
+    }
+
+    /// Whether this is game input, which the sender paces, rather than a
+    /// control message like `pong` that goes out right away.
+    pub fn is_action(&self) -> bool {
+        matches!(
+            self,
+            ClientMessage::Input { .. } | ClientMessage::Key { .. }
+        )
+    }
+
+    /// Rejects messages the server would refuse or silently ignore.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ClientMessage::Register {
+                username, password, ..
+            }
+            | ClientMessage::Login { username, password }
+                if username.is_empty() || password.is_empty() =>
+            {
+                return Err(format!("{} without username or password", self.kind()));
+            }
+            ClientMessage::Play { game_id } if game_id.is_empty() => {
+                return Err("play without game_id".to_string());
+            }
+            ClientMessage::Input { text } if text.is_empty() => {
+                return Err("empty input".to_string());
+            }
+            ClientMessage::Spectate { username } if username.is_empty() => {
+                return Err("watch without username".to_string());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("client messages always serialize")
+    }
 }
 
 #[derive(Debug)]
 pub enum ProcessMessage {
-    Server(Value),
+    Server(ServerMessage),
     Repl(String),
+    /// The websocket dropped; a new connection is being made.
+    Disconnected,
+    /// A new websocket is up after a drop.
+    Reconnected,
 }
 
 pub fn normalize_messages(value: Value) -> Vec<Value> {
@@ -26,3 +506,64 @@ pub fn normalize_messages(value: Value) -> Vec<Value> {
         vec![value]
     }
 }
+
+pub fn parse_messages(value: Value) -> Vec<ServerMessage> {
+    normalize_messages(value)
+        .into_iter()
+        .map(|v| serde_json::from_value(v.clone()).unwrap_or(ServerMessage::Unknown(v)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn assert_lossless(source: &Path, value: Value) {
+        for original in normalize_messages(value) {
+            let parsed: ServerMessage = serde_json::from_value(original.clone()).unwrap();
+            assert!(
+                !matches!(parsed, ServerMessage::Unknown(_)),
+                "{}: '{}' was not recognized",
+                source.display(),
+                parsed.kind()
+            );
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), original);
+        }
+    }
+
+    #[test]
+    fn captures_deserialize_without_loss() {
+        for dir in ["test/research/login", "test/research/move"] {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let raw = fs::read_to_string(&path).unwrap();
+
+                // 00-*.json files are REPL transcripts with one server frame per line.
+                let is_transcript = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("00-"));
+                if is_transcript {
+                    for line in raw.lines() {
+                        if let Some(frame) = line.strip_prefix("[Server]: ") {
+                            assert_lossless(&path, serde_json::from_str(frame).unwrap());
+                        }
+                    }
+                } else {
+                    assert_lossless(&path, serde_json::from_str(&raw).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_messages_keep_raw_json() {
+        let raw = serde_json::json!({"msg": "something_new", "a": [1, 2]});
+        let parsed: ServerMessage = serde_json::from_value(raw.clone()).unwrap();
+        assert!(matches!(parsed, ServerMessage::Unknown(_)));
+        assert_eq!(parsed.kind(), "something_new");
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), raw);
+    }
+}
@@ -0,0 +1,228 @@
+pub mod descend;
+pub mod dive;
+pub mod explore;
+pub mod fight;
+pub mod leave;
+pub mod login;
+pub mod reconnect;
+pub mod registry;
+pub mod rest;
+pub mod start;
+pub mod sweep;
+
+pub use registry::{Launch, Registry};
+
+use crate::account::Account;
+use crate::config::Config;
+use crate::input::InputState;
+use crate::logger::Logger;
+use crate::map::MapState;
+use crate::messages::MessageLog;
+use crate::player::PlayerState;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::ui::UiStack;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Shared game state handed to every routine callback.
+#[derive(Clone)]
+pub struct Context {
+    pub map_state: Arc<Mutex<MapState>>,
+    pub player_state: Arc<Mutex<PlayerState>>,
+    pub message_log: Arc<Mutex<MessageLog>>,
+    pub ui_stack: Arc<Mutex<UiStack>>,
+    pub input: Arc<Mutex<InputState>>,
+    pub account: Arc<Mutex<Account>>,
+    pub config: Arc<Config>,
+    pub logger: Logger,
+}
+
+#[cfg(test)]
+impl Context {
+    /// Empty state with a silent logger, for driving routines in tests.
+    pub fn for_tests() -> Self {
+        let credentials = crate::account::Credentials::new(Some("bot".into()), None, None);
+        let config = Config {
+            url: "ws://localhost:8080/socket".into(),
+            origin: None,
+            game_id: "dcss-web-trunk".into(),
+            seeded_game_id: "seeded-web-trunk".into(),
+            credentials: credentials.clone(),
+            max_actions_per_second: None,
+            log_dir: "logs".into(),
+            routine: None,
+        };
+        Self {
+            map_state: Arc::new(Mutex::new(MapState::new())),
+            player_state: Arc::new(Mutex::new(PlayerState::new())),
+            message_log: Arc::new(Mutex::new(MessageLog::new())),
+            ui_stack: Arc::new(Mutex::new(UiStack::new())),
+            input: Arc::new(Mutex::new(InputState::new())),
+            account: Arc::new(Mutex::new(Account::new(credentials))),
+            config: Arc::new(config),
+            logger: Logger::silent(),
+        }
+    }
+}
+
+/// How urgent a routine is. A routine may only be pre-empted by one with a
+/// strictly higher priority than anything on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Normal,
+    Recovery,
+    Combat,
+}
+
+/// Result a finished routine reports to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure(String),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Success => write!(f, "success"),
+            Outcome::Failure(reason) => write!(f, "failure ({})", reason),
+        }
+    }
+}
+
+pub enum Transition {
+    /// Keep running this routine.
+    Stay,
+    /// Run a sub-routine on top of this one until it finishes.
+    Push(Box<dyn Routine>),
+    /// Pop this routine and hand the outcome to its parent.
+    Finish(Outcome),
+}
+
+/// What a routine callback wants to send and where control goes next.
+pub struct Step {
+    pub messages: Vec<ClientMessage>,
+    pub transition: Transition,
+}
+
+impl Step {
+    pub fn stay(messages: Vec<ClientMessage>) -> Self {
+        Self {
+            messages,
+            transition: Transition::Stay,
+        }
+    }
+
+    pub fn push(routine: Box<dyn Routine>) -> Self {
+        Self {
+            messages: vec![],
+            transition: Transition::Push(routine),
+        }
+    }
+
+    pub fn finish(outcome: Outcome, messages: Vec<ClientMessage>) -> Self {
+        Self {
+            messages,
+            transition: Transition::Finish(outcome),
+        }
+    }
+
+    pub fn success() -> Self {
+        Self::finish(Outcome::Success, vec![])
+    }
+
+    pub fn failure(reason: impl Into<String>) -> Self {
+        Self::finish(Outcome::Failure(reason.into()), vec![])
+    }
+}
+
+/// A behaviour run by the [`Scheduler`](crate::scheduler::Scheduler). Only the
+/// routine on top of the stack receives server messages; the ones below it
+/// wait for their sub-routine to finish.
+#[async_trait]
+pub trait Routine: Send + std::fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    fn priority(&self) -> Priority {
+        Priority::Normal
+    }
+
+    /// Kinds of server messages (`msg` tags) passed to `on_message`; all
+    /// others are skipped while the routine is on top.
+    fn consumes(&self) -> &'static [&'static str];
+
+    /// Kinds of server messages that reach this routine even while
+    /// sub-routines run on top of it. Those sub-routines are cancelled first,
+    /// e.g. when the game ends in the middle of a fight.
+    fn watches(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Called once when the routine is pushed, before any message arrives.
+    async fn on_enter(&mut self, _ctx: &Context) -> Step {
+        Step::stay(vec![])
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step;
+
+    /// Called when the routine leaves the stack, whether finished or cancelled.
+    async fn on_exit(&mut self, _ctx: &Context) {}
+
+    /// Called when a sub-routine on top of this one finished. By default the
+    /// routine starts over as if it had just been entered.
+    async fn on_resume(&mut self, _child: &str, _outcome: &Outcome, ctx: &Context) -> Step {
+        self.on_enter(ctx).await
+    }
+
+    /// Offered every server message while the routine is anywhere on the
+    /// stack. A returned routine pre-empts the current top if its priority is
+    /// higher.
+    async fn interrupt(
+        &mut self,
+        _msg: &ServerMessage,
+        _ctx: &Context,
+    ) -> Option<Box<dyn Routine>> {
+        None
+    }
+}
+
+/// All routines that can be launched from the REPL. A new strategy only needs
+/// its module declared above and a line here.
+pub fn builtin() -> Registry {
+    let mut registry = Registry::new();
+    descend::register(&mut registry);
+    dive::register(&mut registry);
+    explore::register(&mut registry);
+    fight::register(&mut registry);
+    leave::register(&mut registry);
+    login::register(&mut registry);
+    rest::register(&mut registry);
+    start::register(&mut registry);
+    sweep::register(&mut registry);
+    registry
+}
+
+/// The standard reaction to danger while travelling: fight visible hostiles,
+/// otherwise rest when hurt.
+pub async fn threat_response(ctx: &Context) -> Option<Box<dyn Routine>> {
+    let player = ctx.player_state.lock().await;
+    let hostiles = {
+        let map = ctx.map_state.lock().await;
+        fight::hostile_monsters(map.current(), player.pos).len()
+    };
+
+    if hostiles > 0 {
+        Some(Box::new(fight::Fight::default()))
+    } else if rest::needs_rest(&player, &rest::RestConfig::default()) {
+        Some(Box::new(rest::Rest::default()))
+    } else {
+        None
+    }
+}
+
+/// Whether `msg` tells us the game is waiting for the next command, with
+/// everything sent before already processed.
+pub async fn is_ready(msg: &ServerMessage, ctx: &Context) -> bool {
+    matches!(msg, ServerMessage::InputMode(_)) && ctx.input.lock().await.awaiting_command()
+}
@@ -0,0 +1,189 @@
+use crate::logger::Logger;
+use crate::map::glyph::{GlyphKind, MinimapFeature};
+use crate::map::{LevelId, MapCell, Pos};
+use crate::pathfinding;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Routine, Step, is_ready, threat_response};
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the level change after pressing `>`.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+/// Give up after this many interrupted walks or unconfirmed descents.
+const MAX_ATTEMPTS: u8 = 5;
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// Walking to `target`.
+    Travel { target: Pos },
+    /// `>` was pressed, waiting for `place`/`depth` to change.
+    Confirm { deadline: Instant },
+}
+
+/// Walks to the nearest known `>` and takes it, succeeding once the `player`
+/// message reports a different level. Interrupted walks and descents that are
+/// not confirmed in time are retried a few times.
+#[derive(Debug, Clone, Default)]
+pub struct Descend {
+    origin: Option<LevelId>,
+    phase: Option<Phase>,
+    attempts: u8,
+}
+
+async fn player_level(ctx: &Context) -> (LevelId, Pos) {
+    let player = ctx.player_state.lock().await;
+    let level = LevelId {
+        place: player.place.clone(),
+        depth: player.depth,
+    };
+    (level, player.pos)
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register("descend", "/descend", Launch::Replace, |_| {
+        Ok(Box::new(Descend::default()))
+    });
+}
+
+#[async_trait]
+impl Routine for Descend {
+    fn name(&self) -> &'static str {
+        "Descend"
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["player", "msgs", "input_mode"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        let (level, pos) = player_level(ctx).await;
+        self.origin.get_or_insert(level);
+        self.phase = None;
+        self.plan(pos, ctx).await
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        let (level, pos) = player_level(ctx).await;
+        let origin = self.origin.get_or_insert_with(|| level.clone());
+
+        if level != *origin {
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Descend successfully finished, {} -> {}\n",
+                    origin, level
+                ))
+                .await;
+            return Step::success();
+        }
+
+        match self.phase.clone() {
+            None => self.plan(pos, ctx).await,
+            Some(Phase::Travel { target }) => {
+                if !is_ready(msg, ctx).await {
+                    return Step::stay(vec![]);
+                }
+                if pos == target {
+                    return self.press(logger).await;
+                }
+                logger
+                    .log(&format!(
+                        "[ROUTIN]: Descend travel interrupted at {}, stairs at {}\n",
+                        pos, target
+                    ))
+                    .await;
+                self.retry(pos, ctx).await
+            }
+            Some(Phase::Confirm { deadline }) => {
+                // The game took `>` and is waiting again without a new level,
+                // e.g. because something moved us off the stairs.
+                if is_ready(msg, ctx).await {
+                    logger
+                        .log("[ROUTIN]: Descend pressed '>' but stayed on the level\n")
+                        .await;
+                    return self.retry(pos, ctx).await;
+                }
+                if Instant::now() < deadline {
+                    return Step::stay(vec![]);
+                }
+                logger
+                    .log("[ROUTIN]: Descend timed out waiting for the level change\n")
+                    .await;
+                self.retry(pos, ctx).await
+            }
+        }
+    }
+
+    async fn interrupt(&mut self, _msg: &ServerMessage, ctx: &Context) -> Option<Box<dyn Routine>> {
+        threat_response(ctx).await
+    }
+}
+
+impl Descend {
+    async fn retry(&mut self, pos: Pos, ctx: &Context) -> Step {
+        self.attempts += 1;
+        if self.attempts >= MAX_ATTEMPTS {
+            ctx.logger
+                .log(&format!(
+                    "[ROUTIN]: Descend aborted after {} attempts\n",
+                    self.attempts
+                ))
+                .await;
+            return Step::failure(format!("no level change after {} attempts", self.attempts));
+        }
+        self.plan(pos, ctx).await
+    }
+
+    async fn press(&mut self, logger: &Logger) -> Step {
+        logger.log("[ROUTIN]: Descend taking the stairs\n").await;
+        self.phase = Some(Phase::Confirm {
+            deadline: Instant::now() + CONFIRM_TIMEOUT,
+        });
+        Step::stay(vec![ClientMessage::input(">")])
+    }
+
+    /// Walks to the nearest known `>`, or takes it right away when standing on
+    /// it.
+    async fn plan(&mut self, pos: Pos, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        if let Some(Phase::Travel { target, .. }) = self.phase
+            && pos == target
+        {
+            return self.press(logger).await;
+        }
+
+        let map = ctx.map_state.lock().await;
+        let level = map.current();
+        // The glyph under the player is '@', but the minimap keeps the feature.
+        if level.get(pos).and_then(MapCell::minimap_feature) == Some(MinimapFeature::StairDown) {
+            return self.press(logger).await;
+        }
+
+        let route = level
+            .find(GlyphKind::StairsDown)
+            .into_iter()
+            .filter_map(|stairs| {
+                pathfinding::find_path(level, pos, stairs).map(|path| (stairs, path))
+            })
+            .min_by_key(|(_, path)| path.len());
+
+        let Some((target, path)) = route else {
+            logger
+                .log("[ROUTIN]: Descend aborted, no reachable down stairs known\n")
+                .await;
+            return Step::failure("no reachable down stairs known");
+        };
+
+        logger
+            .log(&format!(
+                "[ROUTIN]: Descend walking {} steps to stairs at {}\n",
+                path.len(),
+                target
+            ))
+            .await;
+        let messages = pathfinding::path_to_messages(level, pos, &path);
+        self.phase = Some(Phase::Travel { target });
+        Step::stay(messages)
+    }
+}
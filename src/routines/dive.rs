@@ -0,0 +1,57 @@
+use crate::protocol::ServerMessage;
+use crate::routines::descend::Descend;
+use crate::routines::explore::Explore;
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Outcome, Routine, Step};
+use async_trait::async_trait;
+
+/// Explores each level and takes the stairs down, over and over, until the
+/// game ends or neither exploring nor descending gets anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct Dive;
+
+pub fn register(registry: &mut Registry) {
+    registry.register("dive", "/dive", Launch::Replace, |_| Ok(Box::new(Dive)));
+}
+
+#[async_trait]
+impl Routine for Dive {
+    fn name(&self) -> &'static str {
+        "Dive"
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["game_ended"]
+    }
+
+    fn watches(&self) -> &'static [&'static str] {
+        &["game_ended"]
+    }
+
+    async fn on_enter(&mut self, _ctx: &Context) -> Step {
+        Step::push(Box::new(Explore::default()))
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        match msg {
+            ServerMessage::GameEnded(_) => {
+                ctx.logger
+                    .log("[ROUTIN]: Dive finished, game ended\n")
+                    .await;
+                Step::success()
+            }
+            _ => Step::stay(vec![]),
+        }
+    }
+
+    async fn on_resume(&mut self, child: &str, outcome: &Outcome, _ctx: &Context) -> Step {
+        match (child, outcome) {
+            // A partly explored level is fine as long as the stairs are known.
+            ("Explore", _) => Step::push(Box::new(Descend::default())),
+            ("Descend", Outcome::Success) => Step::push(Box::new(Explore::default())),
+            ("Descend", Outcome::Failure(reason)) => Step::failure(format!("stuck: {}", reason)),
+            // Fights and rests pre-empting us end up here; carry on exploring.
+            _ => Step::push(Box::new(Explore::default())),
+        }
+    }
+}
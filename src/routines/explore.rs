@@ -0,0 +1,174 @@
+use crate::input::InputMode;
+use crate::map::Pos;
+use crate::messages::strip_markup;
+use crate::pathfinding;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::fight::Fight;
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Routine, Step, is_ready, threat_response};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::LazyLock;
+
+const KEY_ESCAPE: i32 = 27;
+/// Give up after this many frontier walks that did not let autoexplore resume.
+const MAX_FALLBACKS: u8 = 10;
+
+static DONE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Done exploring").unwrap());
+static REFUSED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(Partly explored|Could not explore|Not exploring|Nowhere to explore)").unwrap()
+});
+static MONSTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(monsters? (is |are )?(nearby|in view)|comes? into view|There are monsters)")
+        .unwrap()
+});
+
+/// Drives the game's autoexplore (`o`) until the level is explored, walking to
+/// the nearest unexplored frontier ourselves whenever autoexplore refuses.
+#[derive(Debug, Clone, Default)]
+pub struct Explore {
+    /// Frontier walks since autoexplore last made progress on its own.
+    fallbacks: u8,
+    /// Player position and number of known cells when `o` was last sent.
+    autoexplore_from: Option<(Pos, usize)>,
+}
+
+enum Stop {
+    Done,
+    Refused,
+    Monster,
+}
+
+fn stop_reason(msg: &ServerMessage) -> Option<Stop> {
+    let ServerMessage::Msgs(msgs) = msg else {
+        return None;
+    };
+
+    msgs.messages
+        .iter()
+        .map(|m| strip_markup(&m.text))
+        .find_map(|text| {
+            if DONE.is_match(&text) {
+                Some(Stop::Done)
+            } else if REFUSED.is_match(&text) {
+                Some(Stop::Refused)
+            } else if MONSTER.is_match(&text) {
+                Some(Stop::Monster)
+            } else {
+                None
+            }
+        })
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register("explore", "/explore", Launch::Replace, |_| {
+        Ok(Box::new(Explore::default()))
+    });
+}
+
+#[async_trait]
+impl Routine for Explore {
+    fn name(&self) -> &'static str {
+        "Explore"
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["msgs", "input_mode"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        self.autoexplore(ctx).await
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        if let Some(from) = self.autoexplore_from
+            && progress(ctx).await != from
+        {
+            self.autoexplore_from = None;
+            self.fallbacks = 0;
+        }
+
+        match stop_reason(msg) {
+            Some(Stop::Done) => {
+                ctx.logger
+                    .log("[ROUTIN]: Explore successfully finished\n")
+                    .await;
+                return Step::success();
+            }
+            Some(Stop::Monster) => {
+                ctx.logger
+                    .log("[ROUTIN]: Explore stopped, monster in view\n")
+                    .await;
+                return Step::push(Box::new(Fight::default()));
+            }
+            Some(Stop::Refused) => return self.explore_frontier(ctx).await,
+            None => {}
+        }
+
+        match msg {
+            ServerMessage::Msgs(msgs) if msgs.more == Some(true) => {
+                Step::stay(vec![ClientMessage::key(KEY_ESCAPE)])
+            }
+            ServerMessage::InputMode(_) if ctx.input.lock().await.mode() == InputMode::More => {
+                Step::stay(vec![ClientMessage::key(KEY_ESCAPE)])
+            }
+            msg if is_ready(msg, ctx).await => self.autoexplore(ctx).await,
+            _ => Step::stay(vec![]),
+        }
+    }
+
+    async fn interrupt(&mut self, _msg: &ServerMessage, ctx: &Context) -> Option<Box<dyn Routine>> {
+        threat_response(ctx).await
+    }
+}
+
+/// Player position and number of known cells on the level, either of which
+/// changes when autoexplore gets somewhere.
+async fn progress(ctx: &Context) -> (Pos, usize) {
+    let pos = ctx.player_state.lock().await.pos;
+    let known = ctx.map_state.lock().await.current().cells().count();
+    (pos, known)
+}
+
+impl Explore {
+    async fn autoexplore(&mut self, ctx: &Context) -> Step {
+        self.autoexplore_from = Some(progress(ctx).await);
+        Step::stay(vec![ClientMessage::input("o")])
+    }
+
+    async fn explore_frontier(&mut self, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        if self.fallbacks >= MAX_FALLBACKS {
+            logger
+                .log("[ROUTIN]: Explore aborted, frontier keeps being unreachable\n")
+                .await;
+            return Step::failure("frontier keeps being unreachable");
+        }
+
+        let from = ctx.player_state.lock().await.pos;
+        let map = ctx.map_state.lock().await;
+        let level = map.current();
+        let path = pathfinding::find_nearest(level, from, |pos, _| level.is_frontier(pos));
+
+        match path {
+            Some(path) if !path.is_empty() => {
+                logger
+                    .log(&format!(
+                        "[ROUTIN]: Explore walking {} steps to frontier at {}\n",
+                        path.len(),
+                        path[path.len() - 1]
+                    ))
+                    .await;
+                self.fallbacks += 1;
+                self.autoexplore_from = None;
+                Step::stay(pathfinding::path_to_messages(level, from, &path))
+            }
+            _ => {
+                logger
+                    .log("[ROUTIN]: Explore finished, no reachable frontier left\n")
+                    .await;
+                Step::success()
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::inventory::ItemClass;
+use crate::map::glyph::MinimapFeature;
+use crate::map::{LevelMap, MapCell, Pos};
+use crate::messages::SubscriptionId;
+use crate::pathfinding;
+use crate::player::PlayerState;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::registry::{Launch, Registry, parse_fractions};
+use crate::routines::{Context, Priority, Routine, Step, is_ready};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::LazyLock;
+
+const KEY_TAB: i32 = 9;
+const ATT_HOSTILE: i32 = 0;
+
+/// Autofight's answer to Tab when it sees nothing to attack.
+static NO_TARGET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"No target in view").unwrap());
+
+/// Escape items in order of preference, as (name fragment, command key). The
+/// fragments match both singular and plural names ("2 potions of curing").
+const ESCAPE_ITEMS: &[(&str, &str)] = &[
+    ("of heal wounds", "q"),
+    ("of curing", "q"),
+    ("of teleportation", "r"),
+    ("of blinking", "r"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FightConfig {
+    /// Below this HP fraction the bot uses escape items if it has any.
+    pub escape_hp: f32,
+    /// Below this HP fraction the bot backs away instead of attacking.
+    pub retreat_hp: f32,
+}
+
+impl Default for FightConfig {
+    fn default() -> Self {
+        Self {
+            escape_hp: 0.35,
+            retreat_hp: 0.2,
+        }
+    }
+}
+
+/// Attacks the nearest hostile monster until none is left in view, backing off
+/// or using escape items when HP gets low.
+#[derive(Debug, Clone, Default)]
+pub struct Fight {
+    pub config: FightConfig,
+    subscription: Option<SubscriptionId>,
+}
+
+impl Fight {
+    pub fn new(config: FightConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register(
+        "fight",
+        "/fight [escape_hp retreat_hp], e.g. /fight 0.35 0.2",
+        Launch::Push,
+        |args| {
+            let config = match parse_fractions(args)? {
+                Some([escape_hp, retreat_hp]) => FightConfig {
+                    escape_hp,
+                    retreat_hp,
+                },
+                None => FightConfig::default(),
+            };
+            Ok(Box::new(Fight::new(config)))
+        },
+    );
+}
+
+/// Whether the monster in `cell` is hostile, judged by the tile's monster info
+/// and otherwise the minimap colour.
+fn is_hostile(cell: &MapCell) -> bool {
+    match &cell.monster {
+        Some(mon) => mon.att.is_none_or(|att| att == ATT_HOSTILE),
+        None => cell.minimap_feature() == Some(MinimapFeature::MonsterHostile),
+    }
+}
+
+/// Positions of hostile monsters in view, nearest first.
+pub fn hostile_monsters(level: &LevelMap, player: Pos) -> Vec<Pos> {
+    let mut monsters: Vec<Pos> = level
+        .monsters()
+        .filter(|&(pos, cell)| pos != player && is_hostile(cell))
+        .map(|(pos, _)| pos)
+        .collect();
+    monsters.sort_by_key(|pos| (pos.distance(player), *pos));
+    monsters
+}
+
+/// The best escape item carried, or else an unknown potion as a gamble.
+fn escape_item(player: &PlayerState) -> Option<(char, &'static str)> {
+    ESCAPE_ITEMS
+        .iter()
+        .find_map(|&(name, key)| {
+            player
+                .inventory
+                .find_by_name(name)
+                .map(|letter| (letter, key))
+        })
+        .or_else(|| {
+            player
+                .inventory
+                .first_unidentified(ItemClass::Potion)
+                .map(|letter| (letter, "q"))
+        })
+}
+
+/// The adjacent cell that gets furthest away from `threat`.
+fn retreat_step(level: &LevelMap, from: Pos, threat: Pos) -> Option<Pos> {
+    from.neighbours()
+        .filter(|&pos| {
+            level
+                .get(pos)
+                .is_some_and(|cell| pathfinding::step_cost(cell).is_some())
+        })
+        .filter(|pos| pos.distance(threat) > from.distance(threat))
+        .max_by_key(|pos| (pos.distance(threat), std::cmp::Reverse(*pos)))
+}
+
+#[async_trait]
+impl Routine for Fight {
+    fn name(&self) -> &'static str {
+        "Fight"
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Combat
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["msgs", "input_mode"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        {
+            let mut log = ctx.message_log.lock().await;
+            let id = *self
+                .subscription
+                .get_or_insert_with(|| log.subscribe(NO_TARGET.clone()));
+            log.take_matches(id);
+        }
+        self.act(ctx).await
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        let no_target = match self.subscription {
+            Some(id) => !ctx.message_log.lock().await.take_matches(id).is_empty(),
+            None => false,
+        };
+        if no_target {
+            ctx.logger
+                .log("[ROUTIN]: Fight aborted, autofight found no target in view\n")
+                .await;
+            return Step::failure("no target in view");
+        }
+
+        if !is_ready(msg, ctx).await {
+            return Step::stay(vec![]);
+        }
+        self.act(ctx).await
+    }
+
+    async fn on_exit(&mut self, ctx: &Context) {
+        if let Some(id) = self.subscription.take() {
+            ctx.message_log.lock().await.unsubscribe(id);
+        }
+    }
+}
+
+impl Fight {
+    async fn act(&self, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        let player = ctx.player_state.lock().await;
+        let map = ctx.map_state.lock().await;
+        let level = map.current();
+
+        let Some(&target) = hostile_monsters(level, player.pos).first() else {
+            logger
+                .log("[ROUTIN]: Fight successfully finished, no hostiles in view\n")
+                .await;
+            return Step::success();
+        };
+
+        let hp = player.hp_fraction();
+        if hp < self.config.escape_hp
+            && let Some((letter, key)) = escape_item(&player)
+        {
+            let item = player.inventory.get(letter).map_or("", |item| &item.name);
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Fight using escape item {} ({}) at {:.0}% HP\n",
+                    letter,
+                    item,
+                    hp * 100.0
+                ))
+                .await;
+            return Step::stay(vec![
+                ClientMessage::input(key),
+                ClientMessage::input(&letter.to_string()),
+            ]);
+        }
+
+        if hp < self.config.retreat_hp
+            && let Some(step) = retreat_step(level, player.pos, target)
+            && let Some(key) = pathfinding::direction_key(player.pos, step)
+        {
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Fight retreating from {} at {:.0}% HP\n",
+                    target,
+                    hp * 100.0
+                ))
+                .await;
+            return Step::stay(vec![ClientMessage::input(&key.to_string())]);
+        }
+
+        let attack = match pathfinding::direction_key(player.pos, target) {
+            Some(key) => ClientMessage::input(&key.to_string()),
+            None => ClientMessage::key(KEY_TAB),
+        };
+        logger
+            .log(&format!(
+                "[ROUTIN]: Fight attacking monster at {}\n",
+                target
+            ))
+            .await;
+        Step::stay(vec![attack])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Cell;
+    use serde_json::json;
+
+    fn update(level: &mut LevelMap, cells: serde_json::Value) {
+        let cells: Vec<Cell> = serde_json::from_value(cells).unwrap();
+        level.update(&cells);
+    }
+
+    #[test]
+    fn monsters_stay_in_view_until_the_server_clears_them() {
+        let mut level = LevelMap::new();
+        update(
+            &mut level,
+            json!([
+                {"x": 0, "y": 0, "mf": 1, "g": "@"},
+                {"mf": 10, "g": "g", "t": {"mon": {"id": 7, "name": "goblin", "att": 0}}},
+                {"mf": 1, "g": "."},
+                {"mf": 7, "g": "j", "t": {"mon": {"id": 8, "name": "jackal", "att": 4}}},
+            ]),
+        );
+        assert_eq!(
+            hostile_monsters(&level, Pos::new(0, 0)),
+            vec![Pos::new(1, 0)]
+        );
+
+        // The next turn only resends what changed, not the goblin.
+        update(&mut level, json!([{"x": 2, "y": 0, "mf": 1, "g": "."}]));
+        assert_eq!(
+            hostile_monsters(&level, Pos::new(0, 0)),
+            vec![Pos::new(1, 0)]
+        );
+
+        update(
+            &mut level,
+            json!([{"x": 1, "y": 0, "mf": 1, "g": ".", "t": {"mon": null}}]),
+        );
+        assert!(hostile_monsters(&level, Pos::new(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn a_new_minimap_feature_clears_the_monster() {
+        let mut level = LevelMap::new();
+        update(
+            &mut level,
+            json!([{"x": 3, "y": 1, "mf": 10, "g": "r", "t": {"mon": {"id": 1, "att": 0}}}]),
+        );
+        assert_eq!(
+            hostile_monsters(&level, Pos::new(0, 0)),
+            vec![Pos::new(3, 1)]
+        );
+
+        update(&mut level, json!([{"x": 3, "y": 1, "mf": 1, "g": "."}]));
+        assert!(hostile_monsters(&level, Pos::new(0, 0)).is_empty());
+    }
+}
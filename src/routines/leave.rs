@@ -0,0 +1,121 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Routine, Step};
+use crate::ui::{HandlerId, UiContent};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::LazyLock;
+
+const KEY_CTRL_Q: i32 = 17;
+const KEY_CTRL_S: i32 = 19;
+
+/// Confirmation prompts shown in the message window rather than as a popup.
+static CONFIRM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)save game and exit\?|abandon this character and quit").unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum How {
+    /// Ctrl-S: save the game and exit to the lobby.
+    Save,
+    /// Ctrl-Q, confirmed by typing "quit": the character is lost.
+    Abandon,
+}
+
+/// Leaves the running game by saving or abandoning it, and succeeds once the
+/// server is back in the lobby.
+#[derive(Debug, Clone)]
+pub struct Leave {
+    how: How,
+    handler: Option<HandlerId>,
+}
+
+impl Leave {
+    pub fn new(how: How) -> Self {
+        Self { how, handler: None }
+    }
+
+    fn confirm(how: How) -> ClientMessage {
+        match how {
+            How::Save => ClientMessage::input("y"),
+            How::Abandon => ClientMessage::input("quit\r"),
+        }
+    }
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register("save", "/save", Launch::Replace, |_| {
+        Ok(Box::new(Leave::new(How::Save)))
+    });
+    registry.register("abandon", "/abandon", Launch::Replace, |_| {
+        Ok(Box::new(Leave::new(How::Abandon)))
+    });
+}
+
+#[async_trait]
+impl Routine for Leave {
+    fn name(&self) -> &'static str {
+        match self.how {
+            How::Save => "Save",
+            How::Abandon => "Abandon",
+        }
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["msgs", "go_lobby"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        if ctx.account.lock().await.playing.is_none() {
+            return Step::failure("no game running");
+        }
+
+        let how = self.how;
+        let handler =
+            ctx.ui_stack
+                .lock()
+                .await
+                .on_prompt(Box::new(move |layer| match &layer.content {
+                    UiContent::YesNo { .. } | UiContent::TextEntry { .. } => {
+                        Some(vec![Self::confirm(how)])
+                    }
+                    _ => None,
+                }));
+        self.handler = Some(handler);
+
+        let key = match self.how {
+            How::Save => KEY_CTRL_S,
+            How::Abandon => KEY_CTRL_Q,
+        };
+        Step::stay(vec![ClientMessage::key(key)])
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        match msg {
+            ServerMessage::GoLobby => {
+                ctx.logger
+                    .log(&format!(
+                        "[ROUTIN]: {} successfully finished, back in the lobby\n",
+                        self.name()
+                    ))
+                    .await;
+                Step::success()
+            }
+            ServerMessage::Msgs(msgs)
+                if msgs
+                    .messages
+                    .iter()
+                    .any(|entry| CONFIRM.is_match(&entry.text)) =>
+            {
+                Step::stay(vec![Self::confirm(self.how)])
+            }
+            _ => Step::stay(vec![]),
+        }
+    }
+
+    async fn on_exit(&mut self, ctx: &Context) {
+        if let Some(id) = self.handler.take() {
+            ctx.ui_stack.lock().await.remove_handler(id);
+        }
+    }
+}
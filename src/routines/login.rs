@@ -0,0 +1,71 @@
+use crate::protocol::ServerMessage;
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Routine, Step};
+use async_trait::async_trait;
+
+/// Logs in with the configured credentials and registers the account if the
+/// login is refused, e.g. because it does not exist yet.
+#[derive(Debug, Clone, Default)]
+pub struct Login {
+    registering: bool,
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register("login", "/login", Launch::Push, |_| {
+        Ok(Box::new(Login::default()))
+    });
+}
+
+#[async_trait]
+impl Routine for Login {
+    fn name(&self) -> &'static str {
+        "Login"
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["login_success", "login_fail", "register_fail"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        let account = ctx.account.lock().await;
+        ctx.logger
+            .log(&format!(
+                "[ROUTIN]: Login as {}\n",
+                account.credentials.username
+            ))
+            .await;
+        self.registering = false;
+        Step::stay(vec![account.credentials.login()])
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        match msg {
+            ServerMessage::LoginSuccess(_) => {
+                ctx.logger
+                    .log("[ROUTIN]: Login successfully finished\n")
+                    .await;
+                Step::success()
+            }
+            ServerMessage::LoginFail(_) if !self.registering => {
+                let account = ctx.account.lock().await;
+                ctx.logger
+                    .log(&format!(
+                        "[ROUTIN]: Login refused, registering {}\n",
+                        account.credentials.username
+                    ))
+                    .await;
+                self.registering = true;
+                Step::stay(vec![account.credentials.register()])
+            }
+            ServerMessage::LoginFail(_) => Step::failure("login refused after registering"),
+            ServerMessage::RegisterFail(fail) => {
+                let reason = fail.reason.as_deref().unwrap_or("no reason given");
+                ctx.logger
+                    .log(&format!("[ROUTIN]: Login FAILED: {}\n", reason))
+                    .await;
+                Step::failure(format!("registration refused: {}", reason))
+            }
+            _ => Step::stay(vec![]),
+        }
+    }
+}
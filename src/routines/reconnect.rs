@@ -0,0 +1,56 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::login::Login;
+use crate::routines::{Context, Outcome, Priority, Routine, Step, is_ready};
+use async_trait::async_trait;
+
+/// Pushed on top of whatever was running when the connection came back: logs
+/// in again and reattaches to the game that was being played, after which the
+/// interrupted routine starts over on the fresh state.
+#[derive(Debug, Clone, Default)]
+pub struct Reconnect {
+    game_id: Option<String>,
+}
+
+#[async_trait]
+impl Routine for Reconnect {
+    fn name(&self) -> &'static str {
+        "Reconnect"
+    }
+
+    /// Nothing may act before the session is back.
+    fn priority(&self) -> Priority {
+        Priority::Combat
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["input_mode"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        self.game_id = ctx.account.lock().await.playing.clone();
+        Step::push(Box::new(Login::default()))
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        if self.game_id.is_some() && is_ready(msg, ctx).await {
+            ctx.logger
+                .log("[ROUTIN]: Reconnect successfully resumed the game\n")
+                .await;
+            return Step::success();
+        }
+        Step::stay(vec![])
+    }
+
+    async fn on_resume(&mut self, child: &str, outcome: &Outcome, ctx: &Context) -> Step {
+        match (outcome, &self.game_id) {
+            (Outcome::Failure(reason), _) => Step::failure(format!("{} failed: {}", child, reason)),
+            (Outcome::Success, Some(game_id)) => {
+                ctx.logger
+                    .log(&format!("[ROUTIN]: Reconnect resuming {}\n", game_id))
+                    .await;
+                Step::stay(vec![ClientMessage::play(game_id)])
+            }
+            (Outcome::Success, None) => Step::success(),
+        }
+    }
+}
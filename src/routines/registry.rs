@@ -0,0 +1,84 @@
+use crate::routines::Routine;
+use std::collections::BTreeMap;
+
+/// Builds a routine from the arguments that followed its name on the REPL.
+pub type Factory = fn(&[&str]) -> Result<Box<dyn Routine>, String>;
+
+/// How a routine launched by name joins the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launch {
+    /// Cancel whatever is running and start fresh.
+    Replace,
+    /// Run on top of the current routine, which resumes afterwards.
+    Push,
+}
+
+pub struct Entry {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub launch: Launch,
+    factory: Factory,
+}
+
+impl Entry {
+    pub fn build(&self, args: &[&str]) -> Result<Box<dyn Routine>, String> {
+        (self.factory)(args).map_err(|e| format!("{}, usage: {}", e, self.usage))
+    }
+}
+
+/// Routines that can be launched by name. Each routine module registers
+/// itself through a `register` function.
+#[derive(Default)]
+pub struct Registry {
+    entries: BTreeMap<&'static str, Entry>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        launch: Launch,
+        factory: Factory,
+    ) {
+        self.entries.insert(
+            name,
+            Entry {
+                name,
+                usage,
+                launch,
+                factory,
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+}
+
+/// Parses every argument as an `f32`, for routines configured by fractions.
+pub fn parse_fractions<const N: usize>(args: &[&str]) -> Result<Option<[f32; N]>, String> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+    if args.len() != N {
+        return Err(format!("expected {} arguments, got {}", N, args.len()));
+    }
+
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", arg))?;
+    }
+    Ok(Some(values))
+}
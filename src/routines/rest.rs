@@ -0,0 +1,182 @@
+use crate::messages::SubscriptionId;
+use crate::player::PlayerState;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::registry::{Launch, Registry, parse_fractions};
+use crate::routines::{Context, Priority, Routine, Step, fight, is_ready};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static INTERRUPTED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(comes? into view|monsters? (is |are )?nearby|There are monsters|You are (hit|hurt)|appears? out of thin air|You stop resting)",
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestConfig {
+    /// Start resting below this HP fraction.
+    pub hp: f32,
+    /// Start resting below this MP fraction.
+    pub mp: f32,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self { hp: 0.7, mp: 0.5 }
+    }
+}
+
+/// Rests with `5` until HP and MP are full. Resting is abandoned as soon as
+/// a hostile monster shows up, the message log reports an interruption or HP
+/// drops; the parent routine then gets control back.
+#[derive(Debug, Clone, Default)]
+pub struct Rest {
+    pub config: RestConfig,
+    subscription: Option<SubscriptionId>,
+    /// HP after the last rest command, a drop means we were attacked.
+    last_hp: Option<i32>,
+}
+
+impl Rest {
+    pub fn new(config: RestConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register(
+        "rest",
+        "/rest [hp mp], e.g. /rest 0.7 0.5",
+        Launch::Push,
+        |args| {
+            let config = match parse_fractions(args)? {
+                Some([hp, mp]) => RestConfig { hp, mp },
+                None => RestConfig::default(),
+            };
+            Ok(Box::new(Rest::new(config)))
+        },
+    );
+}
+
+/// Whether the player is hurt or drained enough to start resting.
+pub fn needs_rest(player: &PlayerState, config: &RestConfig) -> bool {
+    player.hp_fraction() < config.hp || player.mp_fraction() < config.mp
+}
+
+fn is_recovered(player: &PlayerState) -> bool {
+    player.hp >= player.hp_max && player.mp >= player.mp_max
+}
+
+#[async_trait]
+impl Routine for Rest {
+    fn name(&self) -> &'static str {
+        "Rest"
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Recovery
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["msgs", "player", "input_mode"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        {
+            // Drop interruptions that happened while a sub-routine was running.
+            let mut log = ctx.message_log.lock().await;
+            let id = *self
+                .subscription
+                .get_or_insert_with(|| log.subscribe(INTERRUPTED.clone()));
+            log.take_matches(id);
+        }
+        self.last_hp = None;
+
+        let player = ctx.player_state.lock().await;
+        if !needs_rest(&player, &self.config) {
+            ctx.logger
+                .log("[ROUTIN]: Rest successfully finished, no need to rest\n")
+                .await;
+            return Step::success();
+        }
+        drop(player);
+        self.act(ctx).await
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        let interruption = match self.subscription {
+            Some(id) => ctx.message_log.lock().await.take_matches(id).pop(),
+            None => None,
+        };
+        if let Some(entry) = interruption {
+            logger
+                .log(&format!("[ROUTIN]: Rest interrupted: {}\n", entry.text()))
+                .await;
+            return Step::failure(format!("interrupted: {}", entry.text()));
+        }
+
+        let hp = ctx.player_state.lock().await.hp;
+        if let Some(last_hp) = self.last_hp
+            && hp < last_hp
+        {
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Rest interrupted, HP dropped from {} to {}\n",
+                    last_hp, hp
+                ))
+                .await;
+            return Step::failure("HP dropped");
+        }
+
+        if !is_ready(msg, ctx).await {
+            return Step::stay(vec![]);
+        }
+        self.act(ctx).await
+    }
+
+    async fn on_exit(&mut self, ctx: &Context) {
+        if let Some(id) = self.subscription.take() {
+            ctx.message_log.lock().await.unsubscribe(id);
+        }
+    }
+}
+
+impl Rest {
+    async fn act(&mut self, ctx: &Context) -> Step {
+        let logger = &ctx.logger;
+        let player = ctx.player_state.lock().await;
+        self.last_hp = Some(player.hp);
+
+        let hostiles = {
+            let map = ctx.map_state.lock().await;
+            fight::hostile_monsters(map.current(), player.pos).len()
+        };
+        if hostiles > 0 {
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Rest aborted, {} hostile monster(s) in view\n",
+                    hostiles
+                ))
+                .await;
+            return Step::failure("hostile monsters in view");
+        }
+
+        if is_recovered(&player) {
+            logger
+                .log(&format!(
+                    "[ROUTIN]: Rest successfully finished at HP {}/{} MP {}/{}\n",
+                    player.hp, player.hp_max, player.mp, player.mp_max
+                ))
+                .await;
+            return Step::success();
+        }
+
+        Step::stay(vec![ClientMessage::input("5")])
+    }
+}
@@ -0,0 +1,216 @@
+pub mod character;
+
+use crate::messages::strip_markup;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::login::Login;
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::{Context, Outcome, Routine, Step};
+use crate::ui::{HandlerId, UiContent};
+use async_trait::async_trait;
+use character::CharacterChoice;
+
+pub const DEFAULT_SEED: u64 = 122333;
+const KEY_ENTER: i32 = 13;
+
+/// Logs in if needed and starts a game. A new game picks the configured
+/// species, background and weapon in the new-game menus; an existing save of
+/// the account is resumed instead.
+#[derive(Debug, Clone, Default)]
+pub struct StartGame {
+    seed: Option<u64>,
+    choice: CharacterChoice,
+    handler: Option<HandlerId>,
+}
+
+impl StartGame {
+    pub fn new(choice: CharacterChoice) -> Self {
+        Self {
+            choice,
+            ..Self::default()
+        }
+    }
+
+    pub fn seeded(seed: u64, choice: CharacterChoice) -> Self {
+        Self {
+            seed: Some(seed),
+            choice,
+            ..Self::default()
+        }
+    }
+
+    fn game_id<'a>(&self, ctx: &'a Context) -> &'a str {
+        if self.seed.is_some() {
+            &ctx.config.seeded_game_id
+        } else {
+            &ctx.config.game_id
+        }
+    }
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register(
+        "start",
+        "/start [MiFi [weapon] | species, background[, weapon]]",
+        Launch::Replace,
+        |args| Ok(Box::new(StartGame::new(CharacterChoice::parse(args)?))),
+    );
+    registry.register(
+        "seeded",
+        "/seeded [seed] [MiFi [weapon] | species, background[, weapon]]",
+        Launch::Replace,
+        |args| {
+            let (seed, args) = match args.split_first() {
+                Some((first, rest)) if first.chars().all(|c| c.is_ascii_digit()) => {
+                    let seed = first
+                        .parse()
+                        .map_err(|_| format!("seed '{}' is out of range", first))?;
+                    (seed, rest)
+                }
+                _ => (DEFAULT_SEED, args),
+            };
+            Ok(Box::new(StartGame::seeded(
+                seed,
+                CharacterChoice::parse(args)?,
+            )))
+        },
+    );
+}
+
+#[async_trait]
+impl Routine for StartGame {
+    fn name(&self) -> &'static str {
+        if self.seed.is_some() {
+            "StartSeededGame"
+        } else {
+            "StartGame"
+        }
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &["ui-push", "msgs"]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        let seed = self.seed;
+        let choice = self.choice.clone();
+        let handler =
+            ctx.ui_stack
+                .lock()
+                .await
+                .on_prompt(Box::new(move |layer| match &layer.content {
+                    UiContent::SeedSelection => seed.map(|seed| {
+                        vec![
+                            ClientMessage::input("-"),
+                            ClientMessage::input(&seed.to_string()),
+                            ClientMessage::key(KEY_ENTER),
+                        ]
+                    }),
+                    UiContent::Choice { items } => choice
+                        .select(items)
+                        .ok()
+                        .map(|key| vec![ClientMessage::input(&key.to_string())]),
+                    _ => None,
+                }));
+        self.handler = Some(handler);
+        let seed = self.seed.map(|seed| format!(", seed {}", seed));
+        ctx.logger
+            .log(&format!(
+                "[ROUTIN]: {} as {}{}\n",
+                self.name(),
+                self.choice,
+                seed.unwrap_or_default()
+            ))
+            .await;
+
+        if ctx.account.lock().await.logged_in.is_some() {
+            Step::stay(vec![ClientMessage::play(self.game_id(ctx))])
+        } else {
+            Step::push(Box::new(Login::default()))
+        }
+    }
+
+    async fn on_resume(&mut self, child: &str, outcome: &Outcome, ctx: &Context) -> Step {
+        match (child, outcome) {
+            (_, Outcome::Failure(reason)) => Step::failure(format!("{} failed: {}", child, reason)),
+            ("Login", Outcome::Success) => Step::stay(vec![ClientMessage::play(self.game_id(ctx))]),
+            // The reconnect already reattached to the game, whose welcome
+            // went by while it was on top.
+            ("Reconnect", Outcome::Success) if ctx.account.lock().await.playing.is_some() => {
+                ctx.logger
+                    .log(&format!(
+                        "[ROUTIN]: {} successfully finished after reconnecting\n",
+                        self.name()
+                    ))
+                    .await;
+                Step::success()
+            }
+            ("Reconnect", Outcome::Success) => {
+                Step::stay(vec![ClientMessage::play(self.game_id(ctx))])
+            }
+            (_, Outcome::Success) => Step::stay(vec![]),
+        }
+    }
+
+    async fn on_message(&mut self, msg: &ServerMessage, ctx: &Context) -> Step {
+        match msg {
+            ServerMessage::Msgs(msgs)
+                if msgs
+                    .messages
+                    .iter()
+                    .any(|entry| strip_markup(&entry.text).starts_with("Welcome back")) =>
+            {
+                // A seeded run must start from its seed, not from an old save.
+                if let Some(seed) = self.seed {
+                    let reason = format!("resumed a saved game instead of seed {}", seed);
+                    ctx.logger
+                        .log(&format!("[ROUTIN]: {} FAILED: {}\n", self.name(), reason))
+                        .await;
+                    return Step::failure(reason);
+                }
+                ctx.logger
+                    .log(&format!(
+                        "[ROUTIN]: {} successfully resumed a saved game\n",
+                        self.name()
+                    ))
+                    .await;
+                Step::success()
+            }
+            ServerMessage::UiPush(ui)
+                if ui
+                    .title
+                    .as_deref()
+                    .is_some_and(|title| title.contains("Welcome")) =>
+            {
+                ctx.logger
+                    .log(&format!(
+                        "[ROUTIN]: {} successfully finished\n",
+                        self.name()
+                    ))
+                    .await;
+                Step::success()
+            }
+            ServerMessage::UiPush(_) => {
+                let ui = ctx.ui_stack.lock().await;
+                let Some(UiContent::Choice { items }) = ui.top().map(|layer| &layer.content) else {
+                    return Step::stay(vec![]);
+                };
+                match self.choice.select(items) {
+                    Ok(_) => Step::stay(vec![]),
+                    Err(e) => {
+                        ctx.logger
+                            .log(&format!("[ROUTIN]: {} FAILED: {}\n", self.name(), e))
+                            .await;
+                        Step::failure(e)
+                    }
+                }
+            }
+            _ => Step::stay(vec![]),
+        }
+    }
+
+    async fn on_exit(&mut self, ctx: &Context) {
+        if let Some(id) = self.handler.take() {
+            ctx.ui_stack.lock().await.remove_handler(id);
+        }
+    }
+}
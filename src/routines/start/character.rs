@@ -0,0 +1,320 @@
+use crate::ui::ChoiceItem;
+
+/// Species abbreviations as used in combo names like "MiFi".
+const SPECIES: &[(&str, &str)] = &[
+    ("Ar", "Armataur"),
+    ("Ba", "Barachi"),
+    ("Ce", "Centaur"),
+    ("Co", "Coglin"),
+    ("DE", "Deep Elf"),
+    ("Dg", "Demigod"),
+    ("Dj", "Djinni"),
+    ("Dr", "Draconian"),
+    ("Ds", "Demonspawn"),
+    ("Fe", "Felid"),
+    ("Fo", "Formicid"),
+    ("Gh", "Ghoul"),
+    ("Gn", "Gnoll"),
+    ("Gr", "Gargoyle"),
+    ("Ha", "Halfling"),
+    ("HO", "Hill Orc"),
+    ("Hu", "Human"),
+    ("Ko", "Kobold"),
+    ("MD", "Mountain Dwarf"),
+    ("Mf", "Merfolk"),
+    ("Mi", "Minotaur"),
+    ("Mu", "Mummy"),
+    ("Na", "Naga"),
+    ("Og", "Ogre"),
+    ("On", "Oni"),
+    ("Op", "Octopode"),
+    ("Po", "Poltergeist"),
+    ("Re", "Revenant"),
+    ("Sp", "Spriggan"),
+    ("Te", "Tengu"),
+    ("Tr", "Troll"),
+    ("VS", "Vine Stalker"),
+    ("Vp", "Vampire"),
+];
+
+/// Background abbreviations as used in combo names like "MiFi".
+const BACKGROUNDS: &[(&str, &str)] = &[
+    ("AE", "Air Elementalist"),
+    ("Al", "Alchemist"),
+    ("AK", "Abyssal Knight"),
+    ("Ar", "Artificer"),
+    ("As", "Assassin"),
+    ("Be", "Berserker"),
+    ("Br", "Brigand"),
+    ("CA", "Cinder Acolyte"),
+    ("CK", "Chaos Knight"),
+    ("Cj", "Conjurer"),
+    ("De", "Delver"),
+    ("EE", "Earth Elementalist"),
+    ("En", "Enchanter"),
+    ("FE", "Fire Elementalist"),
+    ("Fi", "Fighter"),
+    ("Gl", "Gladiator"),
+    ("HW", "Hedge Wizard"),
+    ("Hu", "Hunter"),
+    ("IE", "Ice Elementalist"),
+    ("Mo", "Monk"),
+    ("Ne", "Necromancer"),
+    ("Re", "Reaver"),
+    ("Sh", "Shapeshifter"),
+    ("Su", "Summoner"),
+    ("Tm", "Translocator"),
+    ("Wn", "Wanderer"),
+    ("Wr", "Warper"),
+    ("Wz", "Wizard"),
+];
+
+fn lookup(table: &'static [(&str, &'static str)], name: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(abbr, full)| *abbr == name || full.eq_ignore_ascii_case(name))
+        .map(|(_, full)| *full)
+}
+
+/// Splits a combo like "MiFi" into its species and background.
+fn split_combo(combo: &str) -> Option<(&str, &str)> {
+    if combo.len() != 4 || !combo.is_char_boundary(2) {
+        return None;
+    }
+    let (species, background) = combo.split_at(2);
+    let known = |table: &[(&str, &str)], abbr: &str| table.iter().any(|(a, _)| *a == abbr);
+    (known(SPECIES, species) && known(BACKGROUNDS, background)).then_some((species, background))
+}
+
+fn is_known(table: &[(&str, &str)], label: &str) -> bool {
+    table
+        .iter()
+        .any(|(_, full)| full.eq_ignore_ascii_case(label))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Menu {
+    Species,
+    Background,
+    Weapon,
+}
+
+/// The species, background and optional starting weapon to pick in the
+/// new-game menus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterChoice {
+    pub species: String,
+    pub background: String,
+    pub weapon: Option<String>,
+}
+
+impl Default for CharacterChoice {
+    /// Troll Berserker, what always answering `f` used to give.
+    fn default() -> Self {
+        Self {
+            species: "Troll".to_string(),
+            background: "Berserker".to_string(),
+            weapon: None,
+        }
+    }
+}
+
+impl std::fmt::Display for CharacterChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.species, self.background)?;
+        if let Some(weapon) = &self.weapon {
+            write!(f, " with {}", weapon)?;
+        }
+        Ok(())
+    }
+}
+
+impl CharacterChoice {
+    /// Parses `MiFi [weapon]`, `Minotaur Fighter [weapon]` or, for names
+    /// with spaces, `Deep Elf, Fire Elementalist[, weapon]`. No arguments
+    /// give the default.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        if args.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let line = args.join(" ");
+        if line.contains(',') {
+            let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+            return match parts.as_slice() {
+                [species, background] => Self::from_names(species, background, None),
+                [species, background, weapon] => {
+                    Self::from_names(species, background, Some(weapon))
+                }
+                _ => Err("expected species, background[, weapon]".to_string()),
+            };
+        }
+
+        let rest = |from: usize| (args.len() > from).then(|| args[from..].join(" "));
+        if let Some((species, background)) = split_combo(args[0]) {
+            return Self::from_names(species, background, rest(1).as_deref());
+        }
+        if args.len() >= 2 {
+            return Self::from_names(args[0], args[1], rest(2).as_deref());
+        }
+        Err(format!("cannot read '{}' as a character combo", line))
+    }
+
+    fn from_names(species: &str, background: &str, weapon: Option<&str>) -> Result<Self, String> {
+        let species =
+            lookup(SPECIES, species).ok_or_else(|| format!("unknown species '{}'", species))?;
+        let background = lookup(BACKGROUNDS, background)
+            .ok_or_else(|| format!("unknown background '{}'", background))?;
+        Ok(Self {
+            species: species.to_string(),
+            background: background.to_string(),
+            weapon: weapon.filter(|w| !w.is_empty()).map(str::to_string),
+        })
+    }
+
+    fn menu(items: &[ChoiceItem]) -> Menu {
+        if items.iter().any(|item| is_known(SPECIES, &item.label)) {
+            Menu::Species
+        } else if items.iter().any(|item| is_known(BACKGROUNDS, &item.label)) {
+            Menu::Background
+        } else {
+            Menu::Weapon
+        }
+    }
+
+    /// Hotkey of our pick in a new-game menu, or why it cannot be made.
+    /// Without a wanted weapon the first one offered is taken.
+    pub fn select(&self, items: &[ChoiceItem]) -> Result<char, String> {
+        let (what, wanted) = match Self::menu(items) {
+            Menu::Species => ("species", Some(&self.species)),
+            Menu::Background => ("background", Some(&self.background)),
+            Menu::Weapon => ("weapon", self.weapon.as_ref()),
+        };
+
+        let item = match wanted {
+            Some(wanted) => {
+                let wanted = wanted.to_lowercase();
+                items
+                    .iter()
+                    .find(|item| item.label.to_lowercase().starts_with(&wanted))
+            }
+            None => items.iter().find(|item| item.hotkey.is_some()),
+        };
+
+        let Some(item) = item else {
+            let offered: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+            return Err(format!(
+                "{} {} is not available for {}, offered: {}",
+                what,
+                wanted.map_or("(any)", String::as_str),
+                self,
+                offered.join(", ")
+            ));
+        };
+        item.hotkey
+            .ok_or_else(|| format!("{} '{}' has no hotkey", what, item.label))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::UiPushMessage;
+    use crate::ui::{UiContent, UiLayer};
+    use serde_json::json;
+
+    /// Items of a synthetic `newgame-choice` popup. The footer sorts before
+    /// `main-items` in the payload, as it does in the real one.
+    fn menu(title: &str, main: &[(&str, char)]) -> Vec<ChoiceItem> {
+        let buttons: Vec<_> = main
+            .iter()
+            .map(|(label, key)| json!({"label": format!("{} - {}", key, label), "hotkey": *key as u32}))
+            .collect();
+        let push: UiPushMessage = serde_json::from_value(json!({
+            "msg": "ui-push",
+            "type": "newgame-choice",
+            "title": format!("<white>{}</white>", title),
+            "footer": {"buttons": [{"label": "<lightgrey>+ - Recommended</lightgrey>", "hotkey": 43}]},
+            "main-items": {"buttons": buttons},
+            "sub-items": {"buttons": [{"label": "* - Random", "hotkey": 42}]},
+        }))
+        .unwrap();
+        match UiLayer::from_push(&push).content {
+            UiContent::Choice { items } => items,
+            other => panic!("not a choice: {:?}", other),
+        }
+    }
+
+    fn species_menu() -> Vec<ChoiceItem> {
+        menu(
+            "Please select your species.",
+            &[("Minotaur", 'a'), ("Deep Elf", 'b'), ("Troll", 'c')],
+        )
+    }
+
+    fn background_menu() -> Vec<ChoiceItem> {
+        menu(
+            "Please select your background.",
+            &[
+                ("Fighter", 'a'),
+                ("Berserker", 'b'),
+                ("Fire Elementalist", 'c'),
+            ],
+        )
+    }
+
+    fn weapon_menu() -> Vec<ChoiceItem> {
+        menu(
+            "You have a choice of weapons.",
+            &[("mace", 'a'), ("hand axe", 'b'), ("spear", 'c')],
+        )
+    }
+
+    #[test]
+    fn labels_lose_markup_and_hotkey_prefix() {
+        let items = species_menu();
+        assert_eq!(items[0].label, "Minotaur");
+        assert_eq!(items[0].hotkey, Some('a'));
+        assert!(items.iter().any(|item| item.label == "Recommended"));
+    }
+
+    #[test]
+    fn combo_abbreviation() {
+        let choice = CharacterChoice::parse(&["MiFi"]).unwrap();
+        assert_eq!(choice.species, "Minotaur");
+        assert_eq!(choice.background, "Fighter");
+        assert_eq!(choice.weapon, None);
+        assert_eq!(choice.select(&species_menu()), Ok('a'));
+        assert_eq!(choice.select(&background_menu()), Ok('a'));
+    }
+
+    #[test]
+    fn full_names_with_weapon() {
+        let choice = CharacterChoice::parse(&["Minotaur", "Fighter", "hand", "axe"]).unwrap();
+        assert_eq!(choice.weapon.as_deref(), Some("hand axe"));
+        assert_eq!(choice.select(&weapon_menu()), Ok('b'));
+    }
+
+    #[test]
+    fn names_with_spaces_are_split_on_commas() {
+        let choice = CharacterChoice::parse(&["Deep", "Elf,", "Fire", "Elementalist"]).unwrap();
+        assert_eq!(choice.species, "Deep Elf");
+        assert_eq!(choice.background, "Fire Elementalist");
+        assert_eq!(choice.select(&species_menu()), Ok('b'));
+        assert_eq!(choice.select(&background_menu()), Ok('c'));
+    }
+
+    #[test]
+    fn unavailable_combo_fails() {
+        let choice = CharacterChoice::parse(&["FeFi"]).unwrap();
+        let err = choice.select(&species_menu()).unwrap_err();
+        assert!(err.contains("species Felid is not available"), "{}", err);
+        assert!(CharacterChoice::parse(&["XxYy"]).is_err());
+    }
+
+    #[test]
+    fn without_a_weapon_the_first_main_item_is_taken() {
+        let choice = CharacterChoice::parse(&["MiFi"]).unwrap();
+        assert_eq!(choice.select(&weapon_menu()), Ok('a'));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::protocol::ServerMessage;
+use crate::routines::dive::Dive;
+use crate::routines::leave::{How, Leave};
+use crate::routines::registry::{Launch, Registry};
+use crate::routines::start::StartGame;
+use crate::routines::start::character::CharacterChoice;
+use crate::routines::{Context, Outcome, Routine, Step};
+use async_trait::async_trait;
+use chrono::Local;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Refuse ranges that would run for days by accident.
+const MAX_SEEDS: usize = 1000;
+
+/// How one seeded run went.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResult {
+    pub seed: u64,
+    pub combo: String,
+    pub place: String,
+    pub depth: i32,
+    pub turns: i64,
+    pub xl: i32,
+    pub outcome: String,
+    pub cause: String,
+}
+
+/// Plays a list of seeds back to back with [`Dive`] and writes a CSV and JSON
+/// summary after every run.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    seeds: VecDeque<u64>,
+    choice: CharacterChoice,
+    current: Option<u64>,
+    results: Vec<RunResult>,
+    /// Summary path without extension, inside the configured log directory.
+    summary: PathBuf,
+}
+
+impl Sweep {
+    pub fn new(seeds: Vec<u64>, choice: CharacterChoice) -> Self {
+        Self {
+            seeds: seeds.into(),
+            choice,
+            current: None,
+            results: Vec::new(),
+            summary: PathBuf::new(),
+        }
+    }
+}
+
+/// Parses `100-110` (inclusive), `1,5,9` or a single seed.
+pub fn parse_seeds(arg: &str) -> Result<Vec<u64>, String> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<u64>()
+            .map_err(|_| format!("'{}' is not a seed", s))
+    };
+
+    let seeds: Vec<u64> = match arg.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (number(first)?, number(last)?);
+            if first > last {
+                return Err(format!("empty seed range {}", arg));
+            }
+            if last - first >= MAX_SEEDS as u64 {
+                return Err(format!("more than {} seeds in {}", MAX_SEEDS, arg));
+            }
+            (first..=last).collect()
+        }
+        None => arg.split(',').map(number).collect::<Result<_, _>>()?,
+    };
+    if seeds.len() > MAX_SEEDS {
+        return Err(format!("more than {} seeds", MAX_SEEDS));
+    }
+    Ok(seeds)
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register(
+        "sweep",
+        "/sweep <first-last | seed,seed,...> [MiFi [weapon] | species, background[, weapon]]",
+        Launch::Replace,
+        |args| {
+            let (seeds, args) = args
+                .split_first()
+                .ok_or_else(|| "no seeds given".to_string())?;
+            Ok(Box::new(Sweep::new(
+                parse_seeds(seeds)?,
+                CharacterChoice::parse(args)?,
+            )))
+        },
+    );
+}
+
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+impl Sweep {
+    async fn record(&mut self, outcome: &str, cause: String, ctx: &Context) {
+        let Some(seed) = self.current.take() else {
+            return;
+        };
+        let player = ctx.player_state.lock().await;
+        let result = RunResult {
+            seed,
+            combo: self.choice.to_string(),
+            place: player.place.clone(),
+            depth: player.depth,
+            turns: player.turn,
+            xl: player.xl,
+            outcome: outcome.to_string(),
+            cause,
+        };
+        drop(player);
+
+        ctx.logger
+            .log(&format!(
+                "[ROUTIN]: Sweep seed {} {} at {}:{} after {} turns ({})\n",
+                result.seed, result.outcome, result.place, result.depth, result.turns, result.cause
+            ))
+            .await;
+        self.results.push(result);
+
+        if let Err(e) = self.write_summary() {
+            ctx.logger
+                .log(&format!("[ROUTIN]: Sweep could not write summary: {}\n", e))
+                .await;
+        }
+    }
+
+    fn write_summary(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.summary.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut csv = String::from("seed,combo,place,depth,turns,xl,outcome,cause\n");
+        for r in &self.results {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                r.seed,
+                csv_field(&r.combo),
+                csv_field(&r.place),
+                r.depth,
+                r.turns,
+                r.xl,
+                csv_field(&r.outcome),
+                csv_field(&r.cause)
+            ));
+        }
+        std::fs::write(self.summary.with_extension("csv"), csv)?;
+
+        let json = serde_json::to_string_pretty(&self.results)?;
+        std::fs::write(self.summary.with_extension("json"), json)
+    }
+
+    async fn next_seed(&mut self, ctx: &Context) -> Step {
+        let Some(seed) = self.seeds.pop_front() else {
+            ctx.logger
+                .log(&format!(
+                    "[ROUTIN]: Sweep successfully finished {} runs, summary in {}.csv\n",
+                    self.results.len(),
+                    self.summary.display()
+                ))
+                .await;
+            return Step::success();
+        };
+
+        self.current = Some(seed);
+        Step::push(Box::new(StartGame::seeded(seed, self.choice.clone())))
+    }
+}
+
+#[async_trait]
+impl Routine for Sweep {
+    fn name(&self) -> &'static str {
+        "Sweep"
+    }
+
+    fn consumes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    async fn on_enter(&mut self, ctx: &Context) -> Step {
+        self.summary = ctx
+            .config
+            .log_dir
+            .join(format!("sweep-{}", Local::now().format("%Y%m%dT%H%M%S")));
+        self.next_seed(ctx).await
+    }
+
+    async fn on_message(&mut self, _msg: &ServerMessage, _ctx: &Context) -> Step {
+        Step::stay(vec![])
+    }
+
+    async fn on_resume(&mut self, child: &str, outcome: &Outcome, ctx: &Context) -> Step {
+        match (child, outcome) {
+            ("StartSeededGame", Outcome::Success) => Step::push(Box::new(Dive)),
+            ("StartSeededGame", Outcome::Failure(reason)) => {
+                self.record("not started", reason.clone(), ctx).await;
+                // A resumed save is still running and would be resumed again
+                // by every later seed.
+                if ctx.account.lock().await.playing.is_some() {
+                    return Step::push(Box::new(Leave::new(How::Abandon)));
+                }
+                self.next_seed(ctx).await
+            }
+            ("Dive", Outcome::Success) => {
+                let cause = ctx.account.lock().await.ended.clone();
+                self.record("died", cause.unwrap_or_else(|| "unknown".to_string()), ctx)
+                    .await;
+                self.next_seed(ctx).await
+            }
+            ("Dive", Outcome::Failure(reason)) => {
+                // The game is still running; it has to go before the next
+                // seed can start.
+                self.record("stuck", reason.clone(), ctx).await;
+                Step::push(Box::new(Leave::new(How::Abandon)))
+            }
+            ("Abandon", Outcome::Success) => self.next_seed(ctx).await,
+            (_, Outcome::Failure(reason)) => Step::failure(format!(
+                "{} failed with {} seeds left: {}",
+                child,
+                self.seeds.len(),
+                reason
+            )),
+            (_, Outcome::Success) => Step::stay(vec![]),
+        }
+    }
+}
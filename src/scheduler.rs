@@ -0,0 +1,382 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::routines::{Context, Routine, Step, Transition};
+
+/// Runs routines as a stack. The top routine receives the server messages, may
+/// push sub-routines and reports its outcome to the routine below when it
+/// finishes. Any routine on the stack can pre-empt the top with a routine of
+/// higher priority, e.g. a fight interrupting exploration.
+pub struct Scheduler {
+    stack: Vec<Box<dyn Routine>>,
+    ctx: Context,
+}
+
+impl Scheduler {
+    pub fn new(ctx: Context) -> Self {
+        Self {
+            stack: Vec::new(),
+            ctx,
+        }
+    }
+
+    /// Names of the running routines, bottom first, e.g. `Explore > Fight`.
+    pub fn describe(&self) -> String {
+        if self.stack.is_empty() {
+            return "Idle".to_string();
+        }
+        self.stack
+            .iter()
+            .map(|routine| routine.name())
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+
+    /// Cancels everything that is running and starts `routine` on its own.
+    pub async fn start(&mut self, routine: Box<dyn Routine>) -> Vec<ClientMessage> {
+        self.clear().await;
+        self.push(routine).await
+    }
+
+    /// Runs `routine` on top of the current one, which resumes afterwards.
+    pub async fn push(&mut self, routine: Box<dyn Routine>) -> Vec<ClientMessage> {
+        self.apply(Step::push(routine)).await
+    }
+
+    /// Cancels all routines, leaving the scheduler idle.
+    pub async fn clear(&mut self) {
+        self.unwind(0).await;
+    }
+
+    /// Cancels routines from the top until only `len` are left.
+    async fn unwind(&mut self, len: usize) {
+        while self.stack.len() > len
+            && let Some(mut routine) = self.stack.pop()
+        {
+            routine.on_exit(&self.ctx).await;
+            self.ctx
+                .logger
+                .log(&format!("[ROUTIN]: {} cancelled\n", routine.name()))
+                .await;
+        }
+    }
+
+    pub async fn handle(&mut self, msg: &ServerMessage) -> Vec<ClientMessage> {
+        let mut outgoing = self.answer_popup(msg).await;
+        outgoing.extend(self.dispatch(msg).await);
+        outgoing
+    }
+
+    /// Answers a freshly pushed popup through the registered prompt handlers
+    /// while a routine is running; idle, popups are left to the user.
+    async fn answer_popup(&mut self, msg: &ServerMessage) -> Vec<ClientMessage> {
+        if self.stack.is_empty() || !matches!(msg, ServerMessage::UiPush(_)) {
+            return vec![];
+        }
+        let answer = self.ctx.ui_stack.lock().await.answer();
+        let Some(answer) = answer else {
+            return vec![];
+        };
+        if let Some(top) = self.ctx.ui_stack.lock().await.top() {
+            self.ctx
+                .logger
+                .log(&format!(
+                    "[UI    ]: answering {} '{}' with {} message(s)\n",
+                    top.ui_type,
+                    top.title,
+                    answer.len()
+                ))
+                .await;
+        }
+        answer
+    }
+
+    async fn dispatch(&mut self, msg: &ServerMessage) -> Vec<ClientMessage> {
+        let kind = msg.kind();
+        let watcher = self
+            .stack
+            .iter()
+            .rposition(|routine| routine.watches().contains(&kind));
+        if let Some(watcher) = watcher {
+            self.unwind(watcher + 1).await;
+            let step = self.stack[watcher].on_message(msg, &self.ctx).await;
+            return self.apply(step).await;
+        }
+
+        if let Some(routine) = self.preemption(msg).await {
+            self.ctx
+                .logger
+                .log(&format!(
+                    "[ROUTIN]: {} pre-empted by {}\n",
+                    self.describe(),
+                    routine.name()
+                ))
+                .await;
+            return self.push(routine).await;
+        }
+
+        let Some(top) = self.stack.last_mut() else {
+            return vec![];
+        };
+        if !top.consumes().contains(&kind) {
+            return vec![];
+        }
+        let step = top.on_message(msg, &self.ctx).await;
+        self.apply(step).await
+    }
+
+    /// First interrupt offered by a routine on the stack, bottom first, that
+    /// outranks every routine on the stack. Sub-routines run with the priority
+    /// of the routine that pushed them, e.g. the login of a reconnect.
+    async fn preemption(&mut self, msg: &ServerMessage) -> Option<Box<dyn Routine>> {
+        let top_priority = self.stack.iter().map(|routine| routine.priority()).max()?;
+        for routine in self.stack.iter_mut() {
+            if let Some(candidate) = routine.interrupt(msg, &self.ctx).await
+                && candidate.priority() > top_priority
+            {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    /// Follows the transitions of `step` until a routine wants to stay,
+    /// collecting everything the routines want to send on the way.
+    async fn apply(&mut self, mut step: Step) -> Vec<ClientMessage> {
+        let mut outgoing = Vec::new();
+        loop {
+            outgoing.append(&mut step.messages);
+            step = match step.transition {
+                Transition::Stay => break,
+                Transition::Push(mut routine) => {
+                    self.ctx
+                        .logger
+                        .log(&format!(
+                            "[ROUTIN]: {} > {}\n",
+                            self.describe(),
+                            routine.name()
+                        ))
+                        .await;
+                    let step = routine.on_enter(&self.ctx).await;
+                    self.stack.push(routine);
+                    step
+                }
+                Transition::Finish(outcome) => {
+                    let Some(mut finished) = self.stack.pop() else {
+                        break;
+                    };
+                    finished.on_exit(&self.ctx).await;
+                    self.ctx
+                        .logger
+                        .log(&format!(
+                            "[ROUTIN]: {} finished with {}, back to {}\n",
+                            finished.name(),
+                            outcome,
+                            self.describe()
+                        ))
+                        .await;
+                    match self.stack.last_mut() {
+                        Some(parent) => {
+                            parent.on_resume(finished.name(), &outcome, &self.ctx).await
+                        }
+                        None => break,
+                    }
+                }
+            };
+        }
+        outgoing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::{Outcome, Priority};
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    /// A routine that records its callbacks and follows a fixed script.
+    #[derive(Debug)]
+    struct Stub {
+        name: &'static str,
+        priority: Priority,
+        watches: &'static [&'static str],
+        /// Priority of the routine offered as an interrupt on every message.
+        offers: Option<Priority>,
+        finish_on_message: bool,
+        finish_on_resume: bool,
+        events: Events,
+    }
+
+    impl Stub {
+        fn new(name: &'static str, events: &Events) -> Self {
+            Self {
+                name,
+                priority: Priority::Normal,
+                watches: &[],
+                offers: None,
+                finish_on_message: false,
+                finish_on_resume: false,
+                events: events.clone(),
+            }
+        }
+
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[async_trait]
+    impl Routine for Stub {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn priority(&self) -> Priority {
+            self.priority
+        }
+
+        fn consumes(&self) -> &'static [&'static str] {
+            &["ping"]
+        }
+
+        fn watches(&self) -> &'static [&'static str] {
+            self.watches
+        }
+
+        async fn on_message(&mut self, msg: &ServerMessage, _ctx: &Context) -> Step {
+            self.record(format!("{} got {}", self.name, msg.kind()));
+            if self.finish_on_message {
+                Step::success()
+            } else {
+                Step::stay(vec![])
+            }
+        }
+
+        async fn on_exit(&mut self, _ctx: &Context) {
+            self.record(format!("{} exits", self.name));
+        }
+
+        async fn on_resume(&mut self, child: &str, outcome: &Outcome, _ctx: &Context) -> Step {
+            self.record(format!("{} resumes after {} {}", self.name, child, outcome));
+            if self.finish_on_resume {
+                Step::success()
+            } else {
+                Step::stay(vec![])
+            }
+        }
+
+        async fn interrupt(
+            &mut self,
+            _msg: &ServerMessage,
+            _ctx: &Context,
+        ) -> Option<Box<dyn Routine>> {
+            let priority = self.offers?;
+            Some(Box::new(Stub {
+                priority,
+                ..Stub::new("Interrupt", &self.events)
+            }))
+        }
+    }
+
+    async fn stacked(routines: Vec<Stub>) -> Scheduler {
+        let mut scheduler = Scheduler::new(Context::for_tests());
+        for routine in routines {
+            scheduler.push(Box::new(routine)).await;
+        }
+        scheduler
+    }
+
+    #[tokio::test]
+    async fn watcher_cancels_the_routines_above_it() {
+        let events = Events::default();
+        let mut scheduler = stacked(vec![
+            Stub::new("Bottom", &events),
+            Stub {
+                watches: &["go_lobby"],
+                ..Stub::new("Watcher", &events)
+            },
+            Stub::new("Middle", &events),
+            Stub::new("Top", &events),
+        ])
+        .await;
+
+        scheduler.handle(&ServerMessage::GoLobby).await;
+
+        assert_eq!(scheduler.describe(), "Bottom > Watcher");
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["Top exits", "Middle exits", "Watcher got go_lobby"]
+        );
+    }
+
+    #[tokio::test]
+    async fn preemption_needs_a_strictly_higher_priority_than_the_stack() {
+        let events = Events::default();
+        let mut scheduler = stacked(vec![
+            Stub {
+                offers: Some(Priority::Recovery),
+                ..Stub::new("Explore", &events)
+            },
+            Stub {
+                priority: Priority::Recovery,
+                ..Stub::new("Rest", &events)
+            },
+            Stub::new("Travel", &events),
+        ])
+        .await;
+
+        // The top is Normal, but Rest below it is already Recovery.
+        scheduler.handle(&ServerMessage::Ping).await;
+        assert_eq!(scheduler.describe(), "Explore > Rest > Travel");
+        assert_eq!(*events.lock().unwrap(), ["Travel got ping"]);
+
+        let events = Events::default();
+        let mut scheduler = stacked(vec![
+            Stub {
+                offers: Some(Priority::Combat),
+                ..Stub::new("Explore", &events)
+            },
+            Stub {
+                priority: Priority::Recovery,
+                ..Stub::new("Rest", &events)
+            },
+        ])
+        .await;
+
+        scheduler.handle(&ServerMessage::Ping).await;
+        assert_eq!(scheduler.describe(), "Explore > Rest > Interrupt");
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn finished_routines_resume_their_parents_in_turn() {
+        let events = Events::default();
+        let mut scheduler = stacked(vec![
+            Stub::new("Dive", &events),
+            Stub {
+                finish_on_resume: true,
+                ..Stub::new("Descend", &events)
+            },
+            Stub {
+                finish_on_message: true,
+                ..Stub::new("Travel", &events)
+            },
+        ])
+        .await;
+
+        scheduler.handle(&ServerMessage::Ping).await;
+
+        assert_eq!(scheduler.describe(), "Dive");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "Travel got ping",
+                "Travel exits",
+                "Descend resumes after Travel success",
+                "Descend exits",
+                "Dive resumes after Descend success",
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::protocol::ServerMessage;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{Instant, sleep_until, timeout};
+
+/// How long the first action of a batch waits for the previous batch to be
+/// answered before it is sent anyway. Keys that only close a popup may never get an `input_mode`.
+const READY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether `msg` shows the game waiting for input again: any `input_mode`
+/// other than 0 (busy), or a new popup.
+pub fn signals_ready(msg: &ServerMessage) -> bool {
+    match msg {
+        ServerMessage::InputMode(input) => input.mode != 0,
+        ServerMessage::UiPush(_) => true,
+        _ => false,
+    }
+}
+
+/// Paces game input in the sender: each batch of actions waits until the
+/// server signalled readiness after the previous one, and no more than the
+/// configured number of actions go out per second.
+pub struct Throttle {
+    ready: watch::Receiver<u64>,
+    min_interval: Option<Duration>,
+    next_action: Instant,
+}
+
+impl Throttle {
+    /// `ready` is bumped by the receiver for every message that
+    /// [`signals_ready`].
+    pub fn new(mut ready: watch::Receiver<u64>, max_actions_per_second: Option<f64>) -> Self {
+        // Nothing has been sent yet, so nothing to wait for.
+        ready.mark_changed();
+        Self {
+            ready,
+            min_interval: max_actions_per_second
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            next_action: Instant::now(),
+        }
+    }
+
+    /// Waits until the first action of a batch may be sent and marks it as
+    /// sent. Returns false if the server never signalled readiness.
+    pub async fn action(&mut self) -> bool {
+        let ready = self.ready.has_changed().unwrap_or(true)
+            || timeout(READY_TIMEOUT, self.ready.changed()).await.is_ok();
+        self.follow_up().await;
+        ready
+    }
+
+    /// Waits until a further action of the same batch may be sent, which only
+    /// the rate cap holds back.
+    pub async fn follow_up(&mut self) {
+        sleep_until(self.next_action).await;
+
+        self.ready.mark_unchanged();
+        if let Some(interval) = self.min_interval {
+            self.next_action = Instant::now() + interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_the_first_action_of_a_batch_waits_for_readiness() {
+        let (ready, rx) = watch::channel(0);
+        let mut throttle = Throttle::new(rx, None);
+        let start = Instant::now();
+
+        assert!(throttle.action().await);
+        throttle.follow_up().await;
+        throttle.follow_up().await;
+        assert!(start.elapsed() < READY_TIMEOUT / 2);
+
+        ready.send_modify(|count| *count += 1);
+        assert!(throttle.action().await);
+        assert!(start.elapsed() < READY_TIMEOUT / 2);
+    }
+
+    #[tokio::test]
+    async fn rate_cap_spaces_out_every_action() {
+        let (_ready, rx) = watch::channel(0);
+        let mut throttle = Throttle::new(rx, Some(20.0));
+        let start = Instant::now();
+
+        assert!(throttle.action().await);
+        throttle.follow_up().await;
+        throttle.follow_up().await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
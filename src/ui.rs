@@ -0,0 +1,315 @@
+use crate::messages::strip_markup;
+use crate::protocol::{ClientMessage, Fields, ServerMessage, UiPushMessage};
+use regex::Regex;
+use serde_json::Value;
+use std::sync::LazyLock;
+
+const KEY_ESCAPE: i32 = 27;
+
+static YES_NO: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((y/n|Y/n|y/N)\)|\[(y/n|Y/n|y/N)\]").unwrap());
+
+/// One selectable entry of a menu-like popup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceItem {
+    pub hotkey: Option<char>,
+    /// Label without markup or hotkey prefix, e.g. "Minotaur".
+    pub label: String,
+}
+
+/// What a pushed UI layer shows, derived from its `type` and payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiContent {
+    /// Species, background or weapon selection of a new game.
+    Choice {
+        items: Vec<ChoiceItem>,
+    },
+    /// The custom seed screen of seeded games.
+    SeedSelection,
+    /// A line of text to type in, e.g. a name or a count.
+    TextEntry {
+        prompt: String,
+    },
+    /// A prompt answered with `y` or `n`.
+    YesNo {
+        prompt: String,
+    },
+    /// Item, monster, spell or feature descriptions.
+    Describe {
+        body: String,
+    },
+    /// Scrollable text such as help or the welcome screen.
+    Text {
+        body: String,
+    },
+    Other,
+}
+
+/// A popup layer pushed with `ui-push`.
+#[derive(Debug, Clone)]
+pub struct UiLayer {
+    pub ui_type: String,
+    /// Title without colour markup.
+    pub title: String,
+    pub content: UiContent,
+    /// Fields of later `ui-state` updates for this layer.
+    pub state: Fields,
+}
+
+impl UiLayer {
+    pub fn from_push(msg: &UiPushMessage) -> Self {
+        let ui_type = msg.ui_type.clone().unwrap_or_default();
+        let title = msg.title.as_deref().map(strip_markup).unwrap_or_default();
+        let text = |key: &str| {
+            msg.other
+                .get(key)
+                .and_then(Value::as_str)
+                .map(strip_markup)
+                .unwrap_or_default()
+        };
+        let prompt = [text("prompt"), text("body"), title.clone()]
+            .into_iter()
+            .find(|s| !s.is_empty())
+            .unwrap_or_default();
+
+        let content = match ui_type.as_str() {
+            "newgame-choice" => UiContent::Choice {
+                items: choice_items(&msg.other),
+            },
+            "seed-selection" => UiContent::SeedSelection,
+            "msgwin-get-line" => UiContent::TextEntry { prompt },
+            _ if YES_NO.is_match(&prompt) => UiContent::YesNo { prompt },
+            t if t.starts_with("describe-") => UiContent::Describe { body: text("body") },
+            "formatted-scroller" => UiContent::Text { body: text("text") },
+            _ => UiContent::Other,
+        };
+
+        Self {
+            ui_type,
+            title,
+            content,
+            state: Fields::new(),
+        }
+    }
+}
+
+/// Collects every `{label, hotkey}` button of a menu payload, wherever it is
+/// nested (main items, sub items, footer). The payload's keys come out sorted,
+/// so `main-items` is walked first to keep its entries ahead of the footer.
+fn choice_items(payload: &Fields) -> Vec<ChoiceItem> {
+    fn walk(value: &Value, items: &mut Vec<ChoiceItem>) {
+        match value {
+            Value::Object(obj) => {
+                if let Some(label) = obj.get("label").and_then(Value::as_str) {
+                    let hotkey = obj
+                        .get("hotkey")
+                        .and_then(Value::as_u64)
+                        .and_then(|code| char::from_u32(code as u32));
+                    items.push(ChoiceItem {
+                        hotkey,
+                        label: clean_label(label),
+                    });
+                }
+                obj.values().for_each(|v| walk(v, items));
+            }
+            Value::Array(values) => values.iter().for_each(|v| walk(v, items)),
+            _ => {}
+        }
+    }
+
+    let mut items = Vec::new();
+    if let Some(main) = payload.get("main-items") {
+        walk(main, &mut items);
+    }
+    payload
+        .iter()
+        .filter(|(key, _)| *key != "main-items")
+        .for_each(|(_, v)| walk(v, &mut items));
+    items
+}
+
+/// Strips markup and a leading `a - ` hotkey prefix from a menu label.
+fn clean_label(label: &str) -> String {
+    let label = strip_markup(label);
+    let label = label.trim();
+    match label.split_once(" - ") {
+        Some((key, rest)) if key.chars().count() == 1 => rest.trim().to_string(),
+        _ => label.to_string(),
+    }
+}
+
+/// Answers a popup with the messages to send, or `None` to let the next
+/// handler try.
+pub type Handler = Box<dyn Fn(&UiLayer) -> Option<Vec<ClientMessage>> + Send>;
+pub type HandlerId = usize;
+
+/// The popups currently open, fed from `ui-push`/`ui-pop`/`ui-state`
+/// messages, plus the handlers routines registered to answer them.
+#[derive(Default)]
+pub struct UiStack {
+    layers: Vec<UiLayer>,
+    handlers: Vec<(HandlerId, Handler)>,
+    next_handler: HandlerId,
+}
+
+impl UiStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, msg: &ServerMessage) {
+        match msg {
+            ServerMessage::UiPush(push) => self.layers.push(UiLayer::from_push(push)),
+            ServerMessage::UiPop => {
+                self.layers.pop();
+            }
+            ServerMessage::UiPopupState(fields) | ServerMessage::UiStateSync(fields) => {
+                if let Some(top) = self.layers.last_mut() {
+                    top.state
+                        .extend(fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+            }
+            ServerMessage::GoLobby | ServerMessage::GameEnded(_) => self.layers.clear(),
+            _ => {}
+        }
+    }
+
+    /// Forgets the open popups, e.g. when the connection dropped. Handlers
+    /// stay registered.
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn top(&self) -> Option<&UiLayer> {
+        self.layers.last()
+    }
+
+    /// Registers a handler; the most recently registered ones are asked
+    /// first.
+    pub fn on_prompt(&mut self, handler: Handler) -> HandlerId {
+        let id = self.next_handler;
+        self.next_handler += 1;
+        self.handlers.push((id, handler));
+        id
+    }
+
+    pub fn remove_handler(&mut self, id: HandlerId) {
+        self.handlers.retain(|(handler_id, _)| *handler_id != id);
+    }
+
+    /// Answer for the top popup: the first handler that accepts it, or
+    /// escape to close popups nobody expects.
+    pub fn answer(&self) -> Option<Vec<ClientMessage>> {
+        let top = self.top()?;
+        let answer = self
+            .handlers
+            .iter()
+            .rev()
+            .find_map(|(_, handler)| handler(top))
+            .unwrap_or_else(|| vec![ClientMessage::key(KEY_ESCAPE)]);
+        Some(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn push(payload: Value) -> ServerMessage {
+        ServerMessage::UiPush(serde_json::from_value(payload).unwrap())
+    }
+
+    fn layer(payload: Value) -> UiLayer {
+        match push(payload) {
+            ServerMessage::UiPush(msg) => UiLayer::from_push(&msg),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn classifies_popups() {
+        // Typed answers win over the (y/n) hint in the prompt.
+        let typed = layer(json!({
+            "type": "msgwin-get-line",
+            "prompt": "<white>Really quit? (y/n)</white>",
+        }));
+        assert_eq!(
+            typed.content,
+            UiContent::TextEntry {
+                prompt: "Really quit? (y/n)".into()
+            }
+        );
+
+        let yes_no = layer(json!({
+            "type": "query",
+            "title": "Really attack the goblin? [Y/n]",
+        }));
+        assert_eq!(
+            yes_no.content,
+            UiContent::YesNo {
+                prompt: "Really attack the goblin? [Y/n]".into()
+            }
+        );
+
+        let get_line = layer(json!({"type": "msgwin-get-line", "prompt": "What is your name?"}));
+        assert_eq!(
+            get_line.content,
+            UiContent::TextEntry {
+                prompt: "What is your name?".into()
+            }
+        );
+
+        let describe = layer(json!({
+            "type": "describe-monster",
+            "title": "a goblin",
+            "body": "<lightgrey>A small, ugly humanoid.</lightgrey>",
+        }));
+        assert_eq!(describe.title, "a goblin");
+        assert_eq!(
+            describe.content,
+            UiContent::Describe {
+                body: "A small, ugly humanoid.".into()
+            }
+        );
+
+        let unknown = layer(json!({"type": "shopping-list"}));
+        assert_eq!(unknown.content, UiContent::Other);
+    }
+
+    #[test]
+    fn unexpected_popups_are_escaped() {
+        let mut ui = UiStack::new();
+        assert_eq!(ui.answer(), None);
+        ui.update(&push(json!({"type": "shopping-list"})));
+        assert_eq!(ui.answer(), Some(vec![ClientMessage::key(KEY_ESCAPE)]));
+        ui.update(&ServerMessage::UiPop);
+        assert_eq!(ui.answer(), None);
+    }
+
+    #[test]
+    fn latest_handler_answers_first() {
+        let mut ui = UiStack::new();
+        ui.on_prompt(Box::new(|layer| match layer.content {
+            UiContent::YesNo { .. } => Some(vec![ClientMessage::input("n")]),
+            _ => None,
+        }));
+        let yes = ui.on_prompt(Box::new(|layer| match layer.content {
+            UiContent::YesNo { .. } => Some(vec![ClientMessage::input("y")]),
+            _ => None,
+        }));
+        ui.on_prompt(Box::new(|layer| match layer.content {
+            UiContent::TextEntry { .. } => Some(vec![ClientMessage::input("bot\r")]),
+            _ => None,
+        }));
+
+        ui.update(&push(json!({"type": "query", "title": "Really? (y/n)"})));
+        assert_eq!(ui.answer(), Some(vec![ClientMessage::input("y")]));
+
+        ui.remove_handler(yes);
+        assert_eq!(ui.answer(), Some(vec![ClientMessage::input("n")]));
+
+        ui.update(&push(json!({"type": "msgwin-get-line", "prompt": "Name?"})));
+        assert_eq!(ui.answer(), Some(vec![ClientMessage::input("bot\r")]));
+    }
+}